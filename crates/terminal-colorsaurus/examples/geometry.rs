@@ -0,0 +1,18 @@
+//! This example shows how to retrieve the size of the terminal's character cells in pixels.
+
+use terminal_colorsaurus::{terminal_geometry, Error, QueryOptions};
+
+fn main() -> Result<(), display::DisplayAsDebug<Error>> {
+    let geometry = terminal_geometry(QueryOptions::default())?;
+    match geometry.cell_size() {
+        Some(size) => println!("cell: {}x{} px", size.width, size.height),
+        None => println!("cell: unknown"),
+    }
+    if let Some(size) = geometry.text_area_cells {
+        println!("text area: {}x{} cells", size.width, size.height);
+    }
+    Ok(())
+}
+
+#[path = "../examples-utils/display.rs"]
+mod display;
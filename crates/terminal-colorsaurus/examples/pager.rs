@@ -4,8 +4,9 @@
 //! The race condition occurs because the pager and colorsaurus simultaneously
 //! enable/disable raw mode and read/write to the same terminal.
 //!
-//! The heuristic checks if stdout is connected to a pipe which is a strong
-//! indicator that the output is redirected to another process, for instance a pager.
+//! The heuristic used by [`should_query`] checks if we're in the terminal's foreground process group,
+//! if stdout is connected to a pipe (which is a strong indicator that the output
+//! is redirected to another process, for instance a pager) and if a pager is known to be in use.
 //! Note that this heuristic has both
 //! false negatives (output not piped to a pager) and
 //! false positives (stderr piped to a pager).
@@ -18,18 +19,17 @@
 //! 2. `cargo run --example pager | less`—should not print the theme mode.
 //! 3. `cargo run --example pager | cat`—should not print the theme mode. This is a false negatives.
 //! 4. `cargo run --example pager 2>&1 >/dev/tty | less`—should print the theme mode (or error). This is a false positive.
+//! 5. `cargo run --example pager &`—should not print the theme mode.
 
-use std::io::{stdout, IsTerminal as _};
-use terminal_colorsaurus::{theme_mode, Error, QueryOptions};
+use terminal_colorsaurus::{should_query, theme_mode, Error, QueryDecision, QueryOptions};
 
 fn main() -> Result<(), display::DisplayAsDebug<Error>> {
-    if stdout().is_terminal() {
-        eprintln!(
+    match should_query() {
+        QueryDecision::Skip(reason) => eprintln!("No theme mode for you today :/ ({reason})"),
+        _ => eprintln!(
             "Here's the theme mode: {:#?}",
             theme_mode(QueryOptions::default())?
-        );
-    } else {
-        eprintln!("No theme mode for you today :/");
+        ),
     }
 
     Ok(())
@@ -0,0 +1,25 @@
+//! This example shows how to retrieve the terminal's full color palette.
+
+use terminal_colorsaurus::{extended_color_palette, Color, Error, QueryOptions};
+
+fn main() -> Result<(), display::DisplayAsDebug<Error>> {
+    let palette = extended_color_palette(QueryOptions::default())?;
+    println!("foreground: {}", hex(Some(&palette.foreground)));
+    println!("background: {}", hex(Some(&palette.background)));
+    println!("cursor: {}", hex(palette.cursor.as_ref()));
+    println!("selection: {}", hex(palette.selection_background.as_ref()));
+    for (index, color) in palette.ansi.iter().take(16).enumerate() {
+        println!("{index:>3}: {}", hex(color.as_ref()));
+    }
+    Ok(())
+}
+
+fn hex(color: Option<&Color>) -> String {
+    match color.map(Color::scale_to_8bit) {
+        Some((r, g, b)) => format!("#{r:02x}{g:02x}{b:02x}"),
+        None => "unknown".to_owned(),
+    }
+}
+
+#[path = "../examples-utils/display.rs"]
+mod display;
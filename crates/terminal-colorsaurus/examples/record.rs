@@ -0,0 +1,30 @@
+//! This example records the exchange with the terminal as a fixture for the replay tests.
+//!
+//! Run it in the terminal that you want to record and add the output to `tests/fixtures`:
+//! `cargo run --example record > tests/fixtures/my-terminal.txt`
+
+use std::env;
+use terminal_colorsaurus::{recording, Error, QueryOptions, ThemeMode};
+
+fn main() {
+    let (result, recording) = recording::record_color_palette(QueryOptions::default());
+    let expected = match result {
+        Ok(palette) => match palette.theme_mode() {
+            ThemeMode::Dark => "dark",
+            ThemeMode::Light => "light",
+        },
+        Err(Error::Timeout(_)) => "timeout",
+        Err(Error::UnsupportedTerminal(_)) => "unsupported",
+        Err(e) => {
+            eprintln!("error: {e}");
+            "error"
+        }
+    };
+    for var in ["TERM", "TERM_PROGRAM", "TERM_PROGRAM_VERSION"] {
+        if let Ok(value) = env::var(var) {
+            println!("# {var}: {value}");
+        }
+    }
+    println!("# expected: {expected}");
+    print!("{recording}");
+}
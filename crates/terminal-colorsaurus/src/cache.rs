@@ -0,0 +1,392 @@
+//! An opt-in cache that persists the detected colors across processes.
+//!
+//! This is useful for programs that run very often such as shell prompts,
+//! where paying for a round trip to the terminal on every invocation adds up
+//! (especially when connected via SSH).
+//!
+//! Entries are stored in `$XDG_RUNTIME_DIR/terminal-colorsaurus` and are keyed by
+//! the terminal device, the session id and the `TERM` and `TERM_PROGRAM` env vars.
+//! If no cache directory is available (e.g. on Windows), the terminal is queried every time.
+//!
+//! The cache can't notice if the terminal's colors change on its own.
+//! [`theme_mode_report`] always queries the terminal and updates the entry with its reply:
+//! the entry is replaced by the fresh colors or, if the terminal only reported a theme mode
+//! (`CSI ? 997 ; <mode> n`) that contradicts the cached palette, invalidated.
+//! Otherwise, call [`invalidate`] when you know that the colors changed
+//! (e.g. after receiving a color scheme update notification).
+//!
+//! ```no_run
+//! use terminal_colorsaurus::{cache, QueryOptions};
+//!
+//! let theme_mode = cache::theme_mode(QueryOptions::default(), &cache::CacheOptions::default());
+//! ```
+
+use crate::{ColorPalette, QueryOptions, Result, ThemeMode, ThemeModeReport};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Options to be used with the cache.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CacheOptions {
+    /// How long a cached entry is valid. Defaults to [`None`] (forever).
+    pub ttl: Option<Duration>,
+    /// The directory where entries are stored.
+    /// Defaults to `$XDG_RUNTIME_DIR/terminal-colorsaurus`.
+    ///
+    /// The directory should only be accessible by the current user.
+    pub directory: Option<PathBuf>,
+}
+
+/// Same as [`color_palette`](crate::color_palette) but returns the cached
+/// palette if there is one for the current terminal.
+pub fn color_palette(options: QueryOptions, cache: &CacheOptions) -> Result<ColorPalette> {
+    let Some(entry) = CacheEntry::for_current_terminal(cache) else {
+        return crate::color_palette(options);
+    };
+    if let Some(palette) = entry.load(cache.ttl, SystemTime::now()) {
+        return Ok(palette);
+    }
+    let palette = crate::color_palette(options)?;
+    // Failing to write to the cache is not a reason to fail the query.
+    _ = entry.store(&palette, SystemTime::now());
+    Ok(palette)
+}
+
+/// Same as [`theme_mode`](crate::theme_mode) but uses the cached
+/// palette if there is one for the current terminal.
+pub fn theme_mode(options: QueryOptions, cache: &CacheOptions) -> Result<ThemeMode> {
+    color_palette(options, cache).map(|p| p.theme_mode())
+}
+
+/// Removes the cached entry for the current terminal, if any.
+/// The next call to [`color_palette`] or [`theme_mode`] queries the terminal again.
+pub fn invalidate(cache: &CacheOptions) -> io::Result<()> {
+    let Some(entry) = CacheEntry::for_current_terminal(cache) else {
+        return Ok(());
+    };
+    entry.remove()
+}
+
+/// Same as [`theme_mode_report`](crate::theme_mode_report) but also updates the cached entry
+/// for the current terminal with the terminal's reply.
+///
+/// The terminal is always queried. If the reply contains the colors, they replace the cached ones.
+/// If the terminal only reported a theme mode that contradicts the cached palette, the entry is invalidated.
+pub fn theme_mode_report(options: QueryOptions, cache: &CacheOptions) -> Result<ThemeModeReport> {
+    let report = crate::theme_mode_report(options)?;
+    if let Some(entry) = CacheEntry::for_current_terminal(cache) {
+        // Failing to update the cache is not a reason to fail the query.
+        _ = entry.update(&report, SystemTime::now());
+    }
+    Ok(report)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    tty: u64,
+    session: i64,
+    term: String,
+    term_program: String,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    key: CacheKey,
+    path: PathBuf,
+}
+
+impl CacheEntry {
+    fn for_current_terminal(cache: &CacheOptions) -> Option<Self> {
+        let directory = cache.directory.clone().or_else(default_directory)?;
+        Some(Self::new(current_key()?, &directory))
+    }
+
+    fn new(key: CacheKey, directory: &Path) -> Self {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let path = directory.join(format!("palette-{:016x}", hasher.finish()));
+        Self { key, path }
+    }
+
+    fn load(&self, ttl: Option<Duration>, now: SystemTime) -> Option<ColorPalette> {
+        let contents = fs::read_to_string(&self.path).ok()?;
+        let (key, created, palette) = deserialize(&contents)?;
+        let age = now.duration_since(created).unwrap_or_default();
+        if key != self.key || ttl.is_some_and(|ttl| age > ttl) {
+            return None;
+        }
+        Some(palette)
+    }
+
+    fn remove(&self) -> io::Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    fn update(&self, report: &ThemeModeReport, now: SystemTime) -> io::Result<()> {
+        match (&report.palette, report.reported) {
+            (Some(palette), _) => self.store(palette, now),
+            (None, Some(reported)) => self.invalidate_if_changed(reported),
+            (None, None) => Ok(()),
+        }
+    }
+
+    // The terminal reported its theme mode, e.g. in reply to `CSI ? 996 n`.
+    // If it doesn't match the cached palette, the theme changed since the entry was stored.
+    fn invalidate_if_changed(&self, reported: ThemeMode) -> io::Result<()> {
+        let cached = self.load(None, SystemTime::now());
+        if cached.is_some_and(|palette| palette.theme_mode() != reported) {
+            self.remove()
+        } else {
+            Ok(())
+        }
+    }
+
+    // The entry is written to a temporary file first and then renamed
+    // so that concurrent readers never see a partially written entry.
+    // Each write gets its own temporary file, even when storing from multiple threads at once.
+    fn store(&self, palette: &ColorPalette, now: SystemTime) -> io::Result<()> {
+        static WRITES: AtomicUsize = AtomicUsize::new(0);
+        let directory = self.path.parent().unwrap_or(Path::new("."));
+        create_private_dir(directory)?;
+        let temp_path = self.path.with_extension(format!(
+            "{}.{}.tmp",
+            std::process::id(),
+            WRITES.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&temp_path, serialize(&self.key, now, palette))?;
+        let result = fs::rename(&temp_path, &self.path);
+        if result.is_err() {
+            _ = fs::remove_file(&temp_path);
+        }
+        result
+    }
+}
+
+fn serialize(key: &CacheKey, created: SystemTime, palette: &ColorPalette) -> String {
+    let created = created.duration_since(UNIX_EPOCH).unwrap_or_default();
+    format!(
+        "tty={}\nsession={}\nterm={}\nterm_program={}\ncreated={}\nforeground={}\nbackground={}\n",
+        key.tty,
+        key.session,
+        key.term,
+        key.term_program,
+        created.as_secs(),
+        format_color(&palette.foreground),
+        format_color(&palette.background),
+    )
+}
+
+fn deserialize(contents: &str) -> Option<(CacheKey, SystemTime, ColorPalette)> {
+    let mut lines = contents.lines().map(|line| line.split_once('='));
+    let mut field = |name: &str| {
+        lines
+            .next()
+            .flatten()
+            .filter(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    };
+    let key = CacheKey {
+        tty: field("tty")?.parse().ok()?,
+        session: field("session")?.parse().ok()?,
+        term: field("term")?.to_owned(),
+        term_program: field("term_program")?.to_owned(),
+    };
+    let created = UNIX_EPOCH + Duration::from_secs(field("created")?.parse().ok()?);
+    let palette = ColorPalette {
+        foreground: parse_color(field("foreground")?)?,
+        background: parse_color(field("background")?)?,
+    };
+    Some((key, created, palette))
+}
+
+fn format_color(color: &crate::Color) -> String {
+    format!("rgb:{:04x}/{:04x}/{:04x}", color.r, color.g, color.b)
+}
+
+fn parse_color(input: &str) -> Option<crate::Color> {
+    let color = xterm_color::Color::parse(input.as_bytes()).ok()?;
+    Some(crate::Color::rgb(color.red, color.green, color.blue))
+}
+
+fn default_directory() -> Option<PathBuf> {
+    let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR").filter(|d| !d.is_empty())?;
+    Some(PathBuf::from(runtime_dir).join("terminal-colorsaurus"))
+}
+
+#[cfg(unix)]
+fn create_private_dir(directory: &Path) -> io::Result<()> {
+    use std::os::unix::fs::DirBuilderExt as _;
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(directory)
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(directory: &Path) -> io::Result<()> {
+    fs::create_dir_all(directory)
+}
+
+#[cfg(unix)]
+fn current_key() -> Option<CacheKey> {
+    use std::os::fd::AsFd as _;
+    use std::os::unix::fs::MetadataExt as _;
+
+    let terminal = terminal_trx::terminal().ok()?;
+    let tty = fs::File::from(terminal.as_fd().try_clone_to_owned().ok()?)
+        .metadata()
+        .ok()?
+        .rdev();
+    // SAFETY: getsid has no preconditions, passing 0 refers to the calling process.
+    let session = unsafe { libc::getsid(0) };
+    if session == -1 {
+        return None;
+    }
+    Some(CacheKey {
+        tty,
+        session: i64::from(session),
+        term: std::env::var("TERM").unwrap_or_default(),
+        term_program: std::env::var("TERM_PROGRAM").unwrap_or_default(),
+    })
+}
+
+#[cfg(not(unix))]
+fn current_key() -> Option<CacheKey> {
+    None
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::Color;
+
+    fn key() -> CacheKey {
+        CacheKey {
+            tty: 34816,
+            session: 4242,
+            term: "xterm-256color".to_owned(),
+            term_program: "WezTerm".to_owned(),
+        }
+    }
+
+    fn palette() -> ColorPalette {
+        ColorPalette {
+            foreground: Color::rgb(0xffff, 0xeeee, 0xdddd),
+            background: Color::rgb(0x1e1e, 0x1e1e, 0x2e2e),
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "terminal-colorsaurus-test-{name}-{}",
+            std::process::id()
+        ))
+    }
+
+    #[test]
+    fn serialize_round_trip() {
+        let created = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let serialized = serialize(&key(), created, &palette());
+        assert_eq!(Some((key(), created, palette())), deserialize(&serialized));
+    }
+
+    #[test]
+    fn rejects_corrupt_entries() {
+        assert_eq!(None, deserialize(""));
+        assert_eq!(None, deserialize("tty=abc\n"));
+        let serialized = serialize(&key(), UNIX_EPOCH, &palette());
+        assert_eq!(None, deserialize(&serialized.replace("foreground", "fg")));
+    }
+
+    #[test]
+    fn store_and_load() {
+        let directory = temp_dir("store-and-load");
+        let entry = CacheEntry::new(key(), &directory);
+        let now = SystemTime::now();
+        assert_eq!(None, entry.load(None, now));
+
+        entry.store(&palette(), now).unwrap();
+        assert_eq!(Some(palette()), entry.load(None, now));
+
+        let later = now + Duration::from_secs(120);
+        assert_eq!(
+            Some(palette()),
+            entry.load(Some(Duration::from_secs(300)), later)
+        );
+        assert_eq!(None, entry.load(Some(Duration::from_secs(60)), later));
+
+        let other_session = CacheEntry {
+            key: CacheKey {
+                session: 1,
+                ..key()
+            },
+            path: entry.path.clone(),
+        };
+        assert_eq!(None, other_session.load(None, now));
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn invalidates_entry_when_theme_changes() {
+        let directory = temp_dir("theme-change");
+        let entry = CacheEntry::new(key(), &directory);
+        entry.store(&palette(), SystemTime::now()).unwrap();
+
+        entry.invalidate_if_changed(ThemeMode::Dark).unwrap();
+        assert_eq!(Some(palette()), entry.load(None, SystemTime::now()));
+
+        entry.invalidate_if_changed(ThemeMode::Light).unwrap();
+        assert_eq!(None, entry.load(None, SystemTime::now()));
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn updates_entry_from_report() {
+        let directory = temp_dir("report");
+        let entry = CacheEntry::new(key(), &directory);
+        let now = SystemTime::now();
+        let report = |reported, palette| {
+            ThemeModeReport::new(reported, palette, Duration::ZERO).expect("valid report")
+        };
+
+        entry
+            .update(&report(Some(ThemeMode::Light), Some(palette())), now)
+            .unwrap();
+        assert_eq!(Some(palette()), entry.load(None, now));
+
+        entry
+            .update(&report(Some(ThemeMode::Light), None), now)
+            .unwrap();
+        assert_eq!(None, entry.load(None, now));
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn concurrent_stores_do_not_interfere() {
+        let directory = temp_dir("concurrent-stores");
+        let entry = CacheEntry::new(key(), &directory);
+        let now = SystemTime::now();
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| entry.store(&palette(), now).unwrap());
+            }
+        });
+        assert_eq!(Some(palette()), entry.load(None, now));
+        assert_eq!(1, fs::read_dir(&directory).unwrap().count());
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
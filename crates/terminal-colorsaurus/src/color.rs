@@ -33,6 +33,19 @@ impl Color {
         color.perceived_lightness()
     }
 
+    /// The [contrast ratio](https://www.w3.org/TR/WCAG21/#dfn-contrast-ratio) between two colors
+    /// as defined by WCAG 2. Ranges from 1.0 (no contrast) to 21.0 (black on white).
+    /// ```
+    /// # use terminal_colorsaurus::Color;
+    /// let black = Color::rgb(0, 0, 0);
+    /// let white = Color::rgb(u16::MAX, u16::MAX, u16::MAX);
+    /// assert!(black.contrast_ratio(&white) > 20.9);
+    /// ```
+    pub fn contrast_ratio(&self, other: &Color) -> f32 {
+        let color = xterm_color::Color::rgb(self.r, self.g, self.b);
+        color.contrast_ratio(&xterm_color::Color::rgb(other.r, other.g, other.b))
+    }
+
     /// Converts the color to 8 bit precision per channel by scaling each channel.
     ///
     /// ```
@@ -56,6 +69,12 @@ fn scale_to_u8(channel: u16) -> u8 {
     (channel as u32 * (u8::MAX as u32) / (u16::MAX as u32)) as u8
 }
 
+// Scaling up is lossless: `scale_to_u8(scale_to_u16(c)) == c`.
+#[cfg(any(feature = "serde", feature = "owo-colors"))]
+fn scale_to_u16(channel: u8) -> u16 {
+    u16::from(channel) * 0x101
+}
+
 #[cfg(feature = "rgb")]
 impl From<Color> for rgb::RGB16 {
     fn from(value: Color) -> Self {
@@ -94,6 +113,131 @@ impl From<Color> for anstyle::RgbColor {
     }
 }
 
+#[cfg(feature = "crossterm")]
+impl From<Color> for crossterm::style::Color {
+    fn from(value: Color) -> Self {
+        let (r, g, b) = value.scale_to_8bit();
+        crossterm::style::Color::Rgb { r, g, b }
+    }
+}
+
+/// Fails for colors other than [`Rgb`](crossterm::style::Color::Rgb) (e.g. named or indexed colors)
+/// as their actual color depends on the terminal's palette.
+#[cfg(feature = "crossterm")]
+impl TryFrom<crossterm::style::Color> for Color {
+    type Error = NotRgbError;
+
+    fn try_from(value: crossterm::style::Color) -> Result<Self, Self::Error> {
+        match value {
+            crossterm::style::Color::Rgb { r, g, b } => Ok(Color::rgb(
+                scale_to_u16(r),
+                scale_to_u16(g),
+                scale_to_u16(b),
+            )),
+            _ => Err(NotRgbError),
+        }
+    }
+}
+
+#[cfg(feature = "ratatui")]
+impl From<Color> for ratatui::style::Color {
+    fn from(value: Color) -> Self {
+        let (r, g, b) = value.scale_to_8bit();
+        ratatui::style::Color::Rgb(r, g, b)
+    }
+}
+
+/// Fails for colors other than [`Rgb`](ratatui::style::Color::Rgb) (e.g. named or indexed colors)
+/// as their actual color depends on the terminal's palette.
+#[cfg(feature = "ratatui")]
+impl TryFrom<ratatui::style::Color> for Color {
+    type Error = NotRgbError;
+
+    fn try_from(value: ratatui::style::Color) -> Result<Self, Self::Error> {
+        match value {
+            ratatui::style::Color::Rgb(r, g, b) => Ok(Color::rgb(
+                scale_to_u16(r),
+                scale_to_u16(g),
+                scale_to_u16(b),
+            )),
+            _ => Err(NotRgbError),
+        }
+    }
+}
+
+#[cfg(feature = "termcolor")]
+impl From<Color> for termcolor::Color {
+    fn from(value: Color) -> Self {
+        let (r, g, b) = value.scale_to_8bit();
+        termcolor::Color::Rgb(r, g, b)
+    }
+}
+
+/// Fails for colors other than [`Rgb`](termcolor::Color::Rgb) (e.g. named or indexed colors)
+/// as their actual color depends on the terminal's palette.
+#[cfg(feature = "termcolor")]
+impl TryFrom<termcolor::Color> for Color {
+    type Error = NotRgbError;
+
+    fn try_from(value: termcolor::Color) -> Result<Self, Self::Error> {
+        match value {
+            termcolor::Color::Rgb(r, g, b) => Ok(Color::rgb(
+                scale_to_u16(r),
+                scale_to_u16(g),
+                scale_to_u16(b),
+            )),
+            _ => Err(NotRgbError),
+        }
+    }
+}
+
+#[cfg(feature = "owo-colors")]
+impl From<Color> for owo_colors::Rgb {
+    fn from(value: Color) -> Self {
+        let (r, g, b) = value.scale_to_8bit();
+        owo_colors::Rgb(r, g, b)
+    }
+}
+
+#[cfg(feature = "owo-colors")]
+impl From<owo_colors::Rgb> for Color {
+    fn from(value: owo_colors::Rgb) -> Self {
+        let owo_colors::Rgb(r, g, b) = value;
+        Color::rgb(scale_to_u16(r), scale_to_u16(g), scale_to_u16(b))
+    }
+}
+
+#[cfg(feature = "palette")]
+impl From<Color> for palette::Srgb<u16> {
+    fn from(value: Color) -> Self {
+        palette::Srgb::new(value.r, value.g, value.b)
+    }
+}
+
+#[cfg(feature = "palette")]
+impl From<palette::Srgb<u16>> for Color {
+    fn from(value: palette::Srgb<u16>) -> Self {
+        Color::rgb(value.red, value.green, value.blue)
+    }
+}
+
+/// The error returned when converting a color that is not an RGB color
+/// (e.g. a named or an indexed color) to [`Color`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct NotRgbError;
+
+impl std::fmt::Display for NotRgbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("the color is not an RGB color")
+    }
+}
+
+impl std::error::Error for NotRgbError {}
+
+#[cfg(feature = "serde")]
+mod serde;
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -104,6 +248,75 @@ mod tests {
         assert_eq!(0.0, black.perceived_lightness())
     }
 
+    #[test]
+    #[cfg(feature = "owo-colors")]
+    fn owo_colors_round_trip() {
+        let rgb = owo_colors::Rgb(0x12, 0x80, 0xff);
+        let color = Color::from(rgb);
+        assert_eq!(Color::rgb(0x1212, 0x8080, 0xffff), color);
+        assert_eq!(rgb, owo_colors::Rgb::from(color));
+    }
+
+    #[test]
+    #[cfg(feature = "palette")]
+    fn palette_round_trip() {
+        let color = Color::rgb(0x1234, 0x5678, 0x9abc);
+        assert_eq!(
+            color,
+            Color::from(palette::Srgb::<u16>::from(color.clone()))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "crossterm")]
+    fn converts_to_crossterm() {
+        assert_eq!(
+            crossterm::style::Color::Rgb {
+                r: 0xff,
+                g: 0x80,
+                b: 0
+            },
+            crossterm::style::Color::from(Color::rgb(0xffff, 0x8080, 0))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "crossterm")]
+    fn converts_from_crossterm() {
+        let rgb = crossterm::style::Color::Rgb {
+            r: 0xff,
+            g: 0x80,
+            b: 0,
+        };
+        assert_eq!(Ok(Color::rgb(0xffff, 0x8080, 0)), Color::try_from(rgb));
+        assert_eq!(
+            Err(NotRgbError),
+            Color::try_from(crossterm::style::Color::DarkRed)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "ratatui")]
+    fn ratatui_round_trip() {
+        let rgb = ratatui::style::Color::Rgb(0x12, 0x80, 0xff);
+        assert_eq!(
+            Ok(rgb),
+            Color::try_from(rgb).map(ratatui::style::Color::from)
+        );
+        assert_eq!(
+            Err(NotRgbError),
+            Color::try_from(ratatui::style::Color::Indexed(42))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "termcolor")]
+    fn termcolor_round_trip() {
+        let rgb = termcolor::Color::Rgb(0x12, 0x80, 0xff);
+        assert_eq!(Ok(rgb), Color::try_from(rgb).map(termcolor::Color::from));
+        assert_eq!(Err(NotRgbError), Color::try_from(termcolor::Color::Red));
+    }
+
     #[test]
     fn white_has_perceived_lightness_100() {
         let white = Color {
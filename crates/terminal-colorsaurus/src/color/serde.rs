@@ -0,0 +1,231 @@
+use super::{scale_to_u16, Color};
+use core::fmt;
+use serde::de::{self, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{SerializeStruct as _, Serializer};
+use serde::{Deserialize, Serialize};
+
+const FIELDS: &[&str] = &["r", "g", "b"];
+
+/// Human-readable formats (e.g. JSON) use a string: `#rrggbb` if the color
+/// fits into 8 bits per channel and `rgb:rrrr/gggg/bbbb` otherwise.
+/// Other formats use a struct with the fields `r`, `g` and `b`.
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(&ColorString(self))
+        } else {
+            let mut color = serializer.serialize_struct("Color", FIELDS.len())?;
+            color.serialize_field("r", &self.r)?;
+            color.serialize_field("g", &self.g)?;
+            color.serialize_field("b", &self.b)?;
+            color.end()
+        }
+    }
+}
+
+/// Accepts both the string and the struct representation in human-readable formats.
+///
+/// Unlike in X11 color strings, `#rrggbb` is scaled to 16 bits per channel
+/// (i.e. `#ffffff` is white), as is customary on the web and in config files.
+/// All other color strings are parsed using [`xterm_color::Color::parse`].
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(ColorVisitor)
+        } else {
+            deserializer.deserialize_struct("Color", FIELDS, ColorVisitor)
+        }
+    }
+}
+
+struct ColorString<'a>(&'a Color);
+
+impl fmt::Display for ColorString<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Color { r, g, b } = *self.0;
+        if [r, g, b].iter().all(|c| c % 0x101 == 0) {
+            let (r, g, b) = self.0.scale_to_8bit();
+            write!(f, "#{r:02x}{g:02x}{b:02x}")
+        } else {
+            write!(f, "rgb:{r:04x}/{g:04x}/{b:04x}")
+        }
+    }
+}
+
+fn parse_color_string(input: &str) -> Option<Color> {
+    if let Some(hex) = input.strip_prefix('#') {
+        // Checking for hex digits up front also ensures that we don't slice in the middle of a char.
+        if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        let (r, g, b) = (channel(0)?, channel(2)?, channel(4)?);
+        return Some(Color::rgb(
+            scale_to_u16(r),
+            scale_to_u16(g),
+            scale_to_u16(b),
+        ));
+    }
+    let color = xterm_color::Color::parse(input.as_bytes()).ok()?;
+    Some(Color::rgb(color.red, color.green, color.blue))
+}
+
+struct ColorVisitor;
+
+impl<'de> Visitor<'de> for ColorVisitor {
+    type Value = Color;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a color string such as `#rrggbb` or a struct with the fields `r`, `g` and `b`")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        parse_color_string(v).ok_or_else(|| E::invalid_value(de::Unexpected::Str(v), &self))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut channel = |index| {
+            seq.next_element()?
+                .ok_or_else(|| de::Error::invalid_length(index, &self))
+        };
+        Ok(Color::rgb(channel(0)?, channel(1)?, channel(2)?))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let (mut r, mut g, mut b) = (None, None, None);
+        while let Some(key) = map.next_key::<Field>()? {
+            let slot = match key {
+                Field::R => &mut r,
+                Field::G => &mut g,
+                Field::B => &mut b,
+            };
+            if slot.is_some() {
+                return Err(de::Error::duplicate_field(FIELDS[key as usize]));
+            }
+            *slot = Some(map.next_value()?);
+        }
+        Ok(Color::rgb(
+            r.ok_or_else(|| de::Error::missing_field("r"))?,
+            g.ok_or_else(|| de::Error::missing_field("g"))?,
+            b.ok_or_else(|| de::Error::missing_field("b"))?,
+        ))
+    }
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(field_identifier, rename_all = "lowercase")]
+enum Field {
+    R,
+    G,
+    B,
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{ColorPalette, ThemeMode};
+    use serde_test::{assert_tokens, Configure as _, Token};
+
+    #[test]
+    fn serializes_8bit_colors_as_hex() {
+        let color = Color::rgb(0xffff, 0x8080, 0);
+        assert_eq!(r##""#ff8000""##, serde_json::to_string(&color).unwrap());
+    }
+
+    #[test]
+    fn serializes_16bit_colors_as_rgb() {
+        let color = Color::rgb(0xffff, 0x8081, 0);
+        assert_eq!(
+            r#""rgb:ffff/8081/0000""#,
+            serde_json::to_string(&color).unwrap()
+        );
+    }
+
+    #[test]
+    fn deserializes_strings_and_structs() {
+        let expected = Color::rgb(0xffff, 0x8080, 0);
+        for input in [
+            r##""#ff8000""##,
+            r#""rgb:ff/80/00""#,
+            r#""rgb:ffff/8080/0000""#,
+            r#"{"r": 65535, "g": 32896, "b": 0}"#,
+            "[65535, 32896, 0]",
+        ] {
+            assert_eq!(
+                expected,
+                serde_json::from_str::<Color>(input).unwrap(),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_colors() {
+        for input in [
+            r##""#ff80""##,
+            r##""#+f8000""##,
+            r#""red""#,
+            r#"{"r": 0, "g": 0}"#,
+            r#"{"r": 0, "r": 0, "g": 0, "b": 0}"#,
+            "[0, 0]",
+        ] {
+            assert!(serde_json::from_str::<Color>(input).is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn round_trips_as_json() {
+        for color in [
+            Color::rgb(0, 0, 0),
+            Color::rgb(0xffff, 0xffff, 0xffff),
+            Color::rgb(0x1234, 0x5678, 0x9abc),
+        ] {
+            let json = serde_json::to_string(&color).unwrap();
+            assert_eq!(color, serde_json::from_str(&json).unwrap());
+        }
+    }
+
+    #[test]
+    fn uses_struct_in_compact_formats() {
+        assert_tokens(
+            &Color::rgb(1, 2, 3).compact(),
+            &[
+                Token::Struct {
+                    name: "Color",
+                    len: 3,
+                },
+                Token::Str("r"),
+                Token::U16(1),
+                Token::Str("g"),
+                Token::U16(2),
+                Token::Str("b"),
+                Token::U16(3),
+                Token::StructEnd,
+            ],
+        );
+    }
+
+    #[test]
+    fn round_trips_palette_and_theme_mode() {
+        let palette = ColorPalette {
+            foreground: Color::rgb(0xffff, 0xffff, 0xffff),
+            background: Color::rgb(0x1234, 0x5678, 0x9abc),
+        };
+        let json = serde_json::to_string(&palette).unwrap();
+        assert_eq!(
+            r##"{"foreground":"#ffffff","background":"rgb:1234/5678/9abc"}"##,
+            json
+        );
+        assert_eq!(palette, serde_json::from_str(&json).unwrap());
+
+        for mode in [ThemeMode::Dark, ThemeMode::Light] {
+            let json = serde_json::to_string(&mode).unwrap();
+            assert_eq!(mode, serde_json::from_str(&json).unwrap());
+        }
+        assert_eq!(
+            r#""dark""#,
+            serde_json::to_string(&ThemeMode::Dark).unwrap()
+        );
+    }
+}
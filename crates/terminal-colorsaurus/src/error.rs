@@ -4,17 +4,25 @@ use std::time::Duration;
 use std::{error, io};
 
 /// An error returned by this library.
+///
+/// Each variant carries an [`ErrorContext`] (see [`Error::context`])
+/// describing which query failed, what we were doing at the time
+/// and which bytes the terminal had sent so far.
 #[derive(Debug)]
 #[non_exhaustive]
 pub enum Error {
     /// I/O error
-    Io(io::Error),
+    Io(IoError),
     /// The terminal responded using an unsupported response format.
-    Parse(Vec<u8>),
+    Parse(ResponseError),
+    /// The terminal sent something that is not a response to our query
+    /// or a response exceeding the maximum length.
+    /// Contains the offending bytes, truncated to the maximum length.
+    InvalidResponse(ResponseError),
     /// The query timed out. This can happen because \
     /// either the terminal does not support querying for colors \
     /// or the terminal has a lot of latency (e.g. when connected via SSH).
-    Timeout(Duration),
+    Timeout(TimeoutError),
     /// The terminal does not support querying for the foreground or background color.
     UnsupportedTerminal(UnsupportedTerminalError),
 }
@@ -22,7 +30,7 @@ pub enum Error {
 impl error::Error for Error {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match self {
-            Error::Io(source) => Some(source),
+            Error::Io(e) => Some(&e.error),
             Error::UnsupportedTerminal(source) => Some(source),
             _ => None,
         }
@@ -32,42 +40,280 @@ impl error::Error for Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Error::Io(e) => write!(f, "I/O error: {e}"),
-            Error::Parse(data) => write!(
+            Error::Io(e) => write!(f, "I/O error: {}", e.error)?,
+            Error::Parse(e) => write!(
                 f,
                 "failed to parse response: {0}",
                 // FIXME(msrv): [1.79.0] Use `.utf8_chunks()` to avoid allocating.
-                CaretNotation(String::from_utf8_lossy(data).as_ref()),
-            ),
+                CaretNotation(String::from_utf8_lossy(&e.response).as_ref()),
+            )?,
+            Error::InvalidResponse(e) => write!(
+                f,
+                "invalid response: {0}",
+                CaretNotation(String::from_utf8_lossy(&e.response).as_ref()),
+            )?,
             #[allow(clippy::use_debug)]
-            Error::Timeout(timeout) => {
-                write!(f, "operation did not complete within {timeout:?}")
-            }
-            Error::UnsupportedTerminal(e) => fmt::Display::fmt(e, f),
+            Error::Timeout(e) => write!(f, "operation did not complete within {:?}", e.timeout)?,
+            Error::UnsupportedTerminal(e) => fmt::Display::fmt(e, f)?,
+        }
+        let context = self.context();
+        if let Some(phase) = context.phase {
+            write!(f, " while {phase}")?;
+        }
+        if let Some(query) = context.query {
+            write!(f, " (query: {query})")?;
         }
+        Ok(())
     }
 }
 
 impl From<io::Error> for Error {
-    fn from(source: io::Error) -> Self {
-        Error::Io(source)
+    fn from(error: io::Error) -> Self {
+        Error::Io(IoError {
+            error,
+            context: ErrorContext::default(),
+        })
     }
 }
 
 impl Error {
-    pub(crate) fn unsupported() -> Self {
-        Error::UnsupportedTerminal(UnsupportedTerminalError)
+    /// Describes the circumstances of the error.
+    pub fn context(&self) -> &ErrorContext {
+        match self {
+            Error::Io(e) => &e.context,
+            Error::Parse(e) | Error::InvalidResponse(e) => &e.context,
+            Error::Timeout(e) => &e.context,
+            Error::UnsupportedTerminal(e) => &e.context,
+        }
+    }
+
+    fn context_mut(&mut self) -> &mut ErrorContext {
+        match self {
+            Error::Io(e) => &mut e.context,
+            Error::Parse(e) | Error::InvalidResponse(e) => &mut e.context,
+            Error::Timeout(e) => &mut e.context,
+            Error::UnsupportedTerminal(e) => &mut e.context,
+        }
+    }
+
+    pub(crate) fn unsupported(reason: UnsupportedReason) -> Self {
+        Error::UnsupportedTerminal(UnsupportedTerminalError {
+            reason,
+            context: ErrorContext::default(),
+        })
+    }
+
+    pub(crate) fn parse(response: Vec<u8>) -> Self {
+        Error::Parse(ResponseError::new(response))
+    }
+
+    pub(crate) fn invalid_response(response: Vec<u8>) -> Self {
+        Error::InvalidResponse(ResponseError::new(response))
+    }
+
+    pub(crate) fn timeout(timeout: Duration, context: ErrorContext) -> Self {
+        Error::Timeout(TimeoutError { timeout, context })
+    }
+
+    // The context is filled in from the inside out, so we don't overwrite what's already known.
+    pub(crate) fn in_phase(mut self, phase: Phase) -> Self {
+        self.context_mut().phase.get_or_insert(phase);
+        self
+    }
+
+    pub(crate) fn for_query(mut self, query: QueryKind) -> Self {
+        self.context_mut().query.get_or_insert(query);
+        self
+    }
+
+    pub(crate) fn with_received(mut self, received: &[u8]) -> Self {
+        let context = self.context_mut();
+        if context.received.is_empty() {
+            context.received = received.to_vec();
+        }
+        self
+    }
+
+    // `io::Error` is not `Clone`, so we re-create I/O errors from their kind and message.
+    pub(crate) fn duplicate(&self) -> Self {
+        match self {
+            Error::Io(e) => Error::Io(IoError {
+                error: io::Error::new(e.error.kind(), e.error.to_string()),
+                context: e.context.clone(),
+            }),
+            Error::Parse(e) => Error::Parse(e.clone()),
+            Error::InvalidResponse(e) => Error::InvalidResponse(e.clone()),
+            Error::Timeout(e) => Error::Timeout(e.clone()),
+            Error::UnsupportedTerminal(e) => Error::UnsupportedTerminal(e.clone()),
+        }
+    }
+}
+
+/// The circumstances of an [`Error`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ErrorContext {
+    /// The query that failed.
+    /// [`None`] if the error can't be attributed to a single query.
+    pub query: Option<QueryKind>,
+    /// What we were doing when the error occurred.
+    /// [`None`] if the error occurred before talking to the terminal.
+    pub phase: Option<Phase>,
+    /// The bytes received from the terminal before the error occurred,
+    /// truncated to the maximum response length.
+    pub received: Vec<u8>,
+}
+
+/// A query sent to the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum QueryKind {
+    /// The foreground color (`OSC 10`).
+    Foreground,
+    /// The background color (`OSC 11`).
+    Background,
+    /// The cursor color (`OSC 12`).
+    Cursor,
+    /// The selection's background or foreground color (`OSC 17` and `OSC 19`).
+    Selection,
+    /// An indexed color of the 256-color palette (`OSC 4`).
+    Palette(u8),
+    /// All colors at once using the kitty color protocol (`OSC 21`).
+    KittyColors,
+    /// The color scheme (`CSI ? 996 n`).
+    ColorScheme,
+    /// The size of the text area and character cells (`CSI 14 t`, `CSI 16 t` and `CSI 18 t`).
+    Geometry,
+}
+
+impl fmt::Display for QueryKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryKind::Foreground => f.write_str("foreground color"),
+            QueryKind::Background => f.write_str("background color"),
+            QueryKind::Cursor => f.write_str("cursor color"),
+            QueryKind::Selection => f.write_str("selection color"),
+            QueryKind::Palette(index) => write!(f, "palette color {index}"),
+            QueryKind::KittyColors => f.write_str("all colors"),
+            QueryKind::ColorScheme => f.write_str("color scheme"),
+            QueryKind::Geometry => f.write_str("terminal geometry"),
+        }
     }
 }
 
+/// The step of talking to the terminal that failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Phase {
+    /// Opening the terminal device.
+    OpenTerminal,
+    /// Switching the terminal to raw mode.
+    EnableRawMode,
+    /// Writing the query.
+    Write,
+    /// Reading the terminal's reply.
+    Read,
+    /// Parsing the terminal's reply.
+    Parse,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Phase::OpenTerminal => "opening the terminal",
+            Phase::EnableRawMode => "enabling raw mode",
+            Phase::Write => "writing the query",
+            Phase::Read => "reading the reply",
+            Phase::Parse => "parsing the reply",
+        })
+    }
+}
+
+/// The payload of [`Error::Io`].
 #[derive(Debug)]
 #[non_exhaustive]
-pub struct UnsupportedTerminalError;
+pub struct IoError {
+    /// The underlying I/O error.
+    pub error: io::Error,
+    /// See [`Error::context`].
+    pub context: ErrorContext,
+}
+
+/// The payload of [`Error::Parse`] and [`Error::InvalidResponse`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ResponseError {
+    /// The offending response.
+    pub response: Vec<u8>,
+    /// See [`Error::context`].
+    pub context: ErrorContext,
+}
+
+impl ResponseError {
+    fn new(response: Vec<u8>) -> Self {
+        Self {
+            response,
+            context: ErrorContext::default(),
+        }
+    }
+}
+
+/// The payload of [`Error::Timeout`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct TimeoutError {
+    /// The timeout that elapsed.
+    pub timeout: Duration,
+    /// See [`Error::context`].
+    pub context: ErrorContext,
+}
+
+/// The payload of [`Error::UnsupportedTerminal`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct UnsupportedTerminalError {
+    /// How we found out that the terminal is not supported.
+    pub reason: UnsupportedReason,
+    /// See [`Error::context`].
+    pub context: ErrorContext,
+}
 
 impl error::Error for UnsupportedTerminalError {}
 
 impl fmt::Display for UnsupportedTerminalError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str("the terminal does not support querying for its colors")
+        write!(
+            f,
+            "the terminal does not support querying for its colors ({})",
+            self.reason
+        )
+    }
+}
+
+/// How we found out that the terminal is not supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum UnsupportedReason {
+    /// The terminal is known to be unsupported based on
+    /// environment variables such as `TERM` (see [`quirks`](crate::quirks)).
+    /// No query was sent.
+    Quirks,
+    /// The terminal answered the query we send after the color query (`DA1`) first,
+    /// meaning that it ignored the color query.
+    Da1Sentinel,
+    /// The terminal answered, but left out colors that are required.
+    MissingColors,
+    /// Querying the terminal is not supported on this platform.
+    Platform,
+}
+
+impl fmt::Display for UnsupportedReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            UnsupportedReason::Quirks => "known to be unsupported",
+            UnsupportedReason::Da1Sentinel => "the color query was ignored",
+            UnsupportedReason::MissingColors => "the reply is missing colors",
+            UnsupportedReason::Platform => "unsupported platform",
+        })
     }
 }
@@ -0,0 +1,50 @@
+use crate::{Color, ColorPalette, ThemeMode};
+
+/// The number of colors in the terminal's indexed color palette.
+pub const ANSI_PALETTE_LEN: usize = 256;
+
+/// The terminal's full color palette.
+/// Retrieved by calling [`extended_color_palette`](crate::extended_color_palette).
+///
+/// Only the foreground and background color are guaranteed to be present,
+/// all other colors are [`None`] if the terminal didn't report them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct ExtendedColorPalette {
+    /// The foreground color of the terminal.
+    pub foreground: Color,
+    /// The background color of the terminal.
+    pub background: Color,
+    /// The color of the cursor.
+    pub cursor: Option<Color>,
+    /// The color of the text under the cursor.
+    ///
+    /// Only reported by terminals supporting the kitty color protocol (`OSC 21`).
+    pub cursor_text: Option<Color>,
+    /// The foreground color of selected text.
+    pub selection_foreground: Option<Color>,
+    /// The background color of selected text.
+    pub selection_background: Option<Color>,
+    /// Additional background colors that are rendered with transparency.
+    ///
+    /// Only reported by terminals supporting the kitty color protocol (`OSC 21`).
+    pub transparent_backgrounds: Vec<Color>,
+    /// The indexed color palette (`0`–`255`), where the first 16 colors are the ANSI colors.
+    /// Always contains exactly [`ANSI_PALETTE_LEN`] entries.
+    pub ansi: Vec<Option<Color>>,
+}
+
+impl ExtendedColorPalette {
+    /// The foreground and background color.
+    pub fn color_palette(&self) -> ColorPalette {
+        ColorPalette {
+            foreground: self.foreground.clone(),
+            background: self.background.clone(),
+        }
+    }
+
+    /// Determines if the terminal uses a dark or light background.
+    pub fn theme_mode(&self) -> ThemeMode {
+        self.color_palette().theme_mode()
+    }
+}
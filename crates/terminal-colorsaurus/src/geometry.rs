@@ -0,0 +1,53 @@
+/// The size of the terminal's window and its character cells
+/// as reported by the terminal itself.
+/// Retrieved by calling [`terminal_geometry`](crate::terminal_geometry).
+///
+/// Each field is queried separately and is [`None`] if the terminal
+/// did not answer the corresponding query or reported a size of zero.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct TerminalGeometry {
+    /// The size of the text area in pixels (`CSI 14 t`).
+    pub text_area_pixels: Option<Size>,
+    /// The size of a single character cell in pixels (`CSI 16 t`).
+    pub cell_pixels: Option<Size>,
+    /// The size of the text area in characters (`CSI 18 t`),
+    /// i.e. the width is the number of columns and the height the number of rows.
+    pub text_area_cells: Option<Size>,
+}
+
+/// A two-dimensional size.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[allow(clippy::exhaustive_structs)]
+pub struct Size {
+    /// Width
+    pub width: u16,
+    /// Height
+    pub height: u16,
+}
+
+impl TerminalGeometry {
+    /// The size of a single character cell in pixels.
+    ///
+    /// Falls back to dividing the text area's size in pixels by its size in characters
+    /// for terminals that only answer `CSI 14 t` and `CSI 18 t`.
+    ///
+    /// ```
+    /// # use terminal_colorsaurus::{Size, TerminalGeometry};
+    /// let mut geometry = TerminalGeometry::default();
+    /// geometry.text_area_pixels = Some(Size { width: 800, height: 480 });
+    /// geometry.text_area_cells = Some(Size { width: 80, height: 24 });
+    /// assert_eq!(Some(Size { width: 10, height: 20 }), geometry.cell_size());
+    /// ```
+    pub fn cell_size(&self) -> Option<Size> {
+        self.cell_pixels.or_else(|| {
+            let pixels = self.text_area_pixels?;
+            let cells = self.text_area_cells?;
+            Some(Size {
+                width: pixels.width.checked_div(cells.width)?,
+                height: pixels.height.checked_div(cells.height)?,
+            })
+            .filter(|size| size.width != 0 && size.height != 0)
+        })
+    }
+}
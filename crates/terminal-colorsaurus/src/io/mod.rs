@@ -6,3 +6,5 @@ mod read_until;
 pub(crate) use read_until::*;
 mod term_reader;
 pub(crate) use term_reader::*;
+mod transport;
+pub(crate) use transport::*;
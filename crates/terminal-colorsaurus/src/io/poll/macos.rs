@@ -8,6 +8,10 @@ use terminal_trx::Transceive;
 
 // macOS does not support polling /dev/tty using kqueue, so we have to
 // resort to pselect/select. See https://nathancraddock.com/blog/macos-dev-tty-polling/.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(terminal))
+)]
 pub(crate) fn poll_read(terminal: &dyn Transceive, timeout: Duration) -> io::Result<()> {
     if timeout.is_zero() {
         return Err(read_timed_out());
@@ -5,6 +5,10 @@ use std::io;
 use std::time::Duration;
 use terminal_trx::Transceive;
 
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(terminal))
+)]
 pub(crate) fn poll_read(terminal: &dyn Transceive, timeout: Duration) -> io::Result<()> {
     if timeout.is_zero() {
         return Err(read_timed_out());
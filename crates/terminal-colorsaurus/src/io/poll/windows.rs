@@ -6,6 +6,10 @@ use terminal_trx::Transceive;
 use windows_sys::Win32::Foundation::{WAIT_ABANDONED, WAIT_OBJECT_0, WAIT_TIMEOUT};
 use windows_sys::Win32::System::Threading::WaitForSingleObject;
 
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(terminal))
+)]
 pub(crate) fn poll_read(terminal: &dyn Transceive, timeout: Duration) -> io::Result<()> {
     let handle = terminal.input_buffer_handle();
     match unsafe { WaitForSingleObject(handle.as_raw_handle(), timeout.as_millis() as u32) } {
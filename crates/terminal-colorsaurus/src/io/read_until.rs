@@ -1,13 +1,43 @@
 use std::io::{self, BufRead};
 
 // Copied from the standard library with modification
-// to support searching for two bytes.
+// to support searching for two bytes and to stop once `buf` reaches `limit` bytes.
 // https://github.com/rust-lang/rust/blob/e35a56d96f7d9d4422f2b7b00bf0bf282b2ec782/library/std/src/io/mod.rs#L2067
 pub(crate) fn read_until2<R: BufRead + ?Sized>(
     r: &mut R,
     delim1: u8,
     delim2: u8,
     buf: &mut Vec<u8>,
+    limit: usize,
+) -> io::Result<usize> {
+    read_until_with(
+        r,
+        |available| memchr::memchr2(delim1, delim2, available),
+        buf,
+        limit,
+    )
+}
+
+// Same as `read_until2` but searches for the first byte matching a predicate.
+pub(crate) fn read_until_match<R: BufRead + ?Sized>(
+    r: &mut R,
+    predicate: impl Fn(u8) -> bool,
+    buf: &mut Vec<u8>,
+    limit: usize,
+) -> io::Result<usize> {
+    read_until_with(
+        r,
+        |available| available.iter().position(|b| predicate(*b)),
+        buf,
+        limit,
+    )
+}
+
+fn read_until_with<R: BufRead + ?Sized>(
+    r: &mut R,
+    find: impl Fn(&[u8]) -> Option<usize>,
+    buf: &mut Vec<u8>,
+    limit: usize,
 ) -> io::Result<usize> {
     let mut read = 0;
     loop {
@@ -17,12 +47,13 @@ pub(crate) fn read_until2<R: BufRead + ?Sized>(
                 Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
                 Err(e) => return Err(e),
             };
-            if let Some(i) = memchr::memchr2(delim1, delim2, available) {
+            let available = &available[..available.len().min(limit.saturating_sub(buf.len()))];
+            if let Some(i) = find(available) {
                 buf.extend_from_slice(&available[..=i]);
                 (true, i + 1)
             } else {
                 buf.extend_from_slice(available);
-                (false, available.len())
+                (buf.len() >= limit, available.len())
             }
         };
         r.consume(used);
@@ -32,3 +63,26 @@ pub(crate) fn read_until2<R: BufRead + ?Sized>(
         }
     }
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_until_either_delimiter() {
+        let mut buf = Vec::new();
+        let mut r = &b"abc\x07def"[..];
+        assert_eq!(4, read_until2(&mut r, 0x07, 0x1b, &mut buf, 100).unwrap());
+        assert_eq!(b"abc\x07", buf.as_slice());
+    }
+
+    #[test]
+    fn stops_at_limit() {
+        let mut buf = b"ab".to_vec();
+        let mut r = &b"cdefgh"[..];
+        assert_eq!(3, read_until2(&mut r, 0x07, 0x1b, &mut buf, 5).unwrap());
+        assert_eq!(b"abcde", buf.as_slice());
+        assert_eq!(b"fgh", r);
+    }
+}
@@ -1,37 +1,93 @@
-use super::poll_read;
+use super::Transport;
+use crate::trace;
+#[cfg(feature = "tracing")]
+use crate::trace::CaretBytes;
 use std::io;
 use std::time::{Duration, Instant};
-use terminal_trx::Transceive;
 
 #[derive(Debug)]
 pub(crate) struct TermReader<R> {
     inner: R,
     timeout: Duration,
+    extended_timeout: Option<Duration>,
     first_read: Option<Instant>,
+    received: Vec<u8>,
+    received_limit: usize,
 }
 
 impl<R> io::Read for TermReader<R>
 where
-    R: Transceive,
+    R: Transport,
 {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         let timeout = self.remaining_timeout();
-        poll_read(&self.inner, timeout)?;
-        self.inner.read(buf)
+        let read = match self.inner.read_timeout(buf, timeout) {
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => match self.extended_timeout.take() {
+                Some(extended) if extended > self.timeout => {
+                    trace::event!(debug, timeout = ?extended, "extending the timeout");
+                    self.timeout = extended;
+                    let timeout = self.remaining_timeout();
+                    self.inner.read_timeout(buf, timeout)?
+                }
+                _ => return Err(e),
+            },
+            result => result?,
+        };
+        trace::event!(trace, bytes = %CaretBytes(&buf[..read]), "read from terminal");
+        let remaining = self.received_limit.saturating_sub(self.received.len());
+        self.received.extend_from_slice(&buf[..read.min(remaining)]);
+        Ok(read)
     }
 }
 
-impl<R> TermReader<R> {
-    pub(crate) fn new(inner: R, timeout: Duration) -> Self {
+impl<R> TermReader<R>
+where
+    R: Transport,
+{
+    /// Keeps a copy of the first `received_limit` bytes read for error reporting.
+    pub(crate) fn new(inner: R, timeout: Duration, received_limit: usize) -> Self {
         Self {
             inner,
             timeout,
+            extended_timeout: None,
             first_read: None,
+            received: Vec::new(),
+            received_limit,
         }
     }
 
+    /// Changes the timeout, measured from the first read.
+    pub(crate) fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Keeps reading until `timeout` (measured from the first read) when the current timeout
+    /// elapses instead of failing. Whatever was read so far is kept, so parsing can continue.
+    pub(crate) fn extend_timeout_to(&mut self, timeout: Duration) {
+        self.extended_timeout = Some(timeout);
+    }
+
+    /// The current timeout, which reflects an extension.
+    pub(crate) fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// The time elapsed since the first read.
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.first_read
+            .map(|first_read| self.inner.now().saturating_duration_since(first_read))
+            .unwrap_or_default()
+    }
+
+    /// The bytes read so far, truncated to the limit passed to [`TermReader::new`].
+    pub(crate) fn received(&self) -> &[u8] {
+        &self.received
+    }
+
     fn remaining_timeout(&mut self) -> Duration {
-        let first_read = self.first_read.get_or_insert_with(Instant::now);
-        self.timeout.saturating_sub(first_read.elapsed())
+        let now = self.inner.now();
+        let first_read = *self.first_read.get_or_insert(now);
+        self.timeout
+            .saturating_sub(now.saturating_duration_since(first_read))
     }
 }
@@ -0,0 +1,201 @@
+use super::{poll_read, read_timed_out};
+use crate::recording::{RecordedReply, Recording};
+use crate::{Error, Phase, Result};
+use std::io::{self, Read as _};
+use std::time::{Duration, Instant};
+use terminal_trx::RawModeGuard;
+
+/// The channel over which we talk to the terminal.
+pub(crate) trait Transport: io::Write {
+    /// Reads from the terminal, waiting at most `timeout` for data to arrive.
+    fn read_timeout(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize>;
+
+    /// The current time as seen by this transport.
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for &mut T {
+    fn read_timeout(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize> {
+        (**self).read_timeout(buf, timeout)
+    }
+
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+}
+
+impl Transport for RawModeGuard<'_> {
+    fn read_timeout(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize> {
+        poll_read(&*self, timeout)?;
+        self.read(buf)
+    }
+}
+
+/// Establishes the connection to the terminal for the duration of a query.
+/// Queries that need more than one round trip connect once for each.
+pub(crate) trait Connect {
+    fn connect<T>(&mut self, f: impl FnOnce(&mut dyn Transport) -> Result<T>) -> Result<T>;
+}
+
+impl<C: Connect + ?Sized> Connect for &mut C {
+    fn connect<T>(&mut self, f: impl FnOnce(&mut dyn Transport) -> Result<T>) -> Result<T> {
+        (**self).connect(f)
+    }
+}
+
+/// Connects to the terminal and enables raw mode.
+pub(crate) struct Tty;
+
+impl Connect for Tty {
+    fn connect<T>(&mut self, f: impl FnOnce(&mut dyn Transport) -> Result<T>) -> Result<T> {
+        let mut tty =
+            terminal_trx::terminal().map_err(|e| Error::from(e).in_phase(Phase::OpenTerminal))?;
+        let mut tty = tty.lock();
+        let mut tty = tty
+            .enable_raw_mode()
+            .map_err(|e| Error::from(e).in_phase(Phase::EnableRawMode))?;
+        f(&mut tty)
+    }
+}
+
+/// Records everything written to and read from the terminal.
+pub(crate) struct Record<'a, C> {
+    inner: C,
+    recording: &'a mut Recording,
+    // Shared by all round trips, so that the replies' arrival times keep increasing.
+    first_read: Option<Instant>,
+}
+
+impl<'a, C> Record<'a, C> {
+    pub(crate) fn new(inner: C, recording: &'a mut Recording) -> Self {
+        Self {
+            inner,
+            recording,
+            first_read: None,
+        }
+    }
+}
+
+impl<C: Connect> Connect for Record<'_, C> {
+    fn connect<T>(&mut self, f: impl FnOnce(&mut dyn Transport) -> Result<T>) -> Result<T> {
+        let recording = &mut *self.recording;
+        let first_read = &mut self.first_read;
+        self.inner.connect(|inner| {
+            f(&mut Recorder {
+                inner,
+                recording,
+                first_read,
+            })
+        })
+    }
+}
+
+struct Recorder<'a, 'b> {
+    inner: &'a mut dyn Transport,
+    recording: &'b mut Recording,
+    first_read: &'b mut Option<Instant>,
+}
+
+impl io::Write for Recorder<'_, '_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.recording.query.extend_from_slice(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Transport for Recorder<'_, '_> {
+    fn read_timeout(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize> {
+        let first_read = *self.first_read.get_or_insert_with(Instant::now);
+        let read = self.inner.read_timeout(buf, timeout)?;
+        self.recording.replies.push(RecordedReply {
+            elapsed: first_read.elapsed(),
+            bytes: buf[..read].to_vec(),
+        });
+        Ok(read)
+    }
+}
+
+/// Plays back a recording. Time is simulated, so replaying is instant and deterministic.
+///
+/// Writing anything other than the recorded query fails, so that
+/// changes to the queries are caught.
+pub(crate) struct Replay<'a> {
+    pub(crate) recording: &'a Recording,
+}
+
+impl Connect for Replay<'_> {
+    fn connect<T>(&mut self, f: impl FnOnce(&mut dyn Transport) -> Result<T>) -> Result<T> {
+        f(&mut Replayer {
+            recording: self.recording,
+            written: 0,
+            next_reply: 0,
+            reply_offset: 0,
+            start: Instant::now(),
+            elapsed: None,
+        })
+    }
+}
+
+struct Replayer<'a> {
+    recording: &'a Recording,
+    written: usize,
+    next_reply: usize,
+    reply_offset: usize,
+    start: Instant,
+    // The simulated time since the first read.
+    elapsed: Option<Duration>,
+}
+
+impl io::Write for Replayer<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let expected = &self.recording.query[self.written.min(self.recording.query.len())..];
+        if !expected.starts_with(buf) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the query differs from the recorded query",
+            ));
+        }
+        self.written += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for Replayer<'_> {
+    fn read_timeout(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize> {
+        let elapsed = self.elapsed.get_or_insert(Duration::ZERO);
+        let deadline = elapsed.saturating_add(timeout);
+        match self.recording.replies.get(self.next_reply) {
+            Some(reply) if reply.elapsed <= deadline => {
+                *elapsed = (*elapsed).max(reply.elapsed);
+                let remaining = &reply.bytes[self.reply_offset..];
+                let read = remaining.len().min(buf.len());
+                buf[..read].copy_from_slice(&remaining[..read]);
+                self.reply_offset += read;
+                if self.reply_offset == reply.bytes.len() {
+                    self.next_reply += 1;
+                    self.reply_offset = 0;
+                }
+                Ok(read)
+            }
+            _ => {
+                *elapsed = deadline;
+                Err(read_timed_out())
+            }
+        }
+    }
+
+    fn now(&self) -> Instant {
+        self.start + self.elapsed.unwrap_or_default()
+    }
+}
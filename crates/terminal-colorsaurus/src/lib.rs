@@ -14,6 +14,11 @@
 //! * Does not send any escape sequences if `TERM=dumb`.
 //! * Works even if all of stderr, stdout and stdin are redirected.
 //! * Supports a timeout (for situations with high latency such as an SSH connection).
+//! * Retrieves the full color palette in a single round trip on terminals supporting the kitty color protocol (`OSC 21`).
+//! * Optionally caches the detected colors across processes (see [`cache`])
+//!   or within a process (see [`memoized`]).
+//! * Queries the terminal's size in pixels and the size of its character cells.
+//! * Heuristically detects when the terminal is shared with a pager (see [`should_query`]).
 //!
 //! ## Terminal Support
 //! `terminal-colorsaurus` works with most modern terminals and has been [tested extensively](`terminal_survey`).
@@ -38,17 +43,53 @@
 //! ## Optional Dependencies
 //! * [`rgb`] — Enable this feature to convert between [`Color`] and [`rgb::RGB16`] / [`rgb::RGB8`].
 //! * [`anstyle`] — Enable this feature to convert [`Color`] to [`anstyle::RgbColor`].
+//! * [`crossterm`] — Enable this feature to convert between [`Color`] and [`crossterm::style::Color`].
+//! * [`ratatui`] — Enable this feature to convert between [`Color`] and [`ratatui::style::Color`].
+//! * [`termcolor`] — Enable this feature to convert between [`Color`] and [`termcolor::Color`].
+//! * [`owo-colors`](owo_colors) — Enable this feature to convert between [`Color`] and [`owo_colors::Rgb`].
+//! * [`palette`] — Enable this feature to convert between [`Color`] and [`palette::Srgb<u16>`](palette::Srgb).
+//! * [`serde`] — Enable this feature to serialize and deserialize [`Color`], [`ColorPalette`] and [`ThemeMode`].
+//! * [`tracing`] — Enable this feature to instrument the queries, the detection of terminal quirks
+//!   and the raw bytes exchanged with the terminal.
+//!
+//! Converting the color types of `crossterm`, `ratatui` and `termcolor` to [`Color`] fails for colors
+//! that are not RGB colors (e.g. named or indexed colors).
+//! None of these crates has a type for dark or light themes, so there are no conversions for [`ThemeMode`].
 
 use cfg_if::cfg_if;
 
+pub mod cache;
 mod color;
+#[cfg_attr(
+    not(all(any(unix, windows), not(terminal_colorsaurus_test_unsupported))),
+    allow(dead_code)
+)]
 mod error;
+mod extended_palette;
 mod fmt;
+mod geometry;
+pub mod memoized;
+#[cfg_attr(
+    not(all(any(unix, windows), not(terminal_colorsaurus_test_unsupported))),
+    allow(dead_code)
+)]
+pub mod quirks;
+pub mod recording;
+mod should_query;
+#[cfg_attr(
+    not(all(any(unix, windows), not(terminal_colorsaurus_test_unsupported))),
+    allow(dead_code)
+)]
+mod theme_mode_report;
+#[cfg_attr(
+    not(all(any(unix, windows), not(terminal_colorsaurus_test_unsupported))),
+    allow(dead_code, unused_imports, unused_macros)
+)]
+mod trace;
 
 cfg_if! {
     if #[cfg(all(any(unix, windows), not(terminal_colorsaurus_test_unsupported)))] {
         mod io;
-        mod quirks;
         mod xterm;
         use xterm as imp;
     } else {
@@ -82,11 +123,16 @@ cfg_if! {
 pub mod readme_doctests {}
 
 pub use color::*;
+pub use extended_palette::*;
+pub use geometry::*;
+pub use should_query::*;
+pub use theme_mode_report::*;
 
 /// The subset of the terminal's color palette needed for
 /// deriving the [`ThemeMode`], namely: the foreground and background color.
 /// Retrieved by calling [`color_palette`].
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[non_exhaustive]
 pub struct ColorPalette {
     /// The foreground color of the terminal.
@@ -99,6 +145,11 @@ pub struct ColorPalette {
 ///
 /// You can retrieve it using [`theme_mode`].
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
 #[allow(clippy::exhaustive_enums)]
 #[doc(alias = "color scheme")]
 pub enum ThemeMode {
@@ -125,7 +176,10 @@ impl ColorPalette {
 
 /// Result used by this library.
 pub type Result<T> = std::result::Result<T, Error>;
-pub use error::Error;
+pub use error::{
+    Error, ErrorContext, IoError, Phase, QueryKind, ResponseError, TimeoutError, UnsupportedReason,
+    UnsupportedTerminalError,
+};
 
 /// Options to be used with [`foreground_color`] and [`background_color`].
 /// You should almost always use the unchanged [`QueryOptions::default`] value.
@@ -142,21 +196,92 @@ pub struct QueryOptions {
     ///
     /// See [Feature Detection](`feature_detection`) for details on how this works.
     pub timeout: std::time::Duration,
+
+    /// Additionally ask the terminal for its color scheme using `CSI ? 996 n`
+    /// when calling [`theme_mode`] or [`theme_mode_report`]. Defaults to `false`.
+    ///
+    /// Terminals supporting this query report dark or light directly, which takes
+    /// precedence over the theme mode derived from the foreground and background color.
+    /// The query is sent in the same round trip as `OSC 10` and `OSC 11`.
+    pub query_color_scheme: bool,
+
+    /// The string terminator used to terminate `OSC` queries.
+    /// Defaults to [`StringTerminatorOption::Auto`] which picks the terminator
+    /// based on the terminal's [quirks](`quirks`).
+    ///
+    /// Responses are accepted with either terminator regardless of this setting.
+    pub string_terminator: quirks::StringTerminatorOption,
+
+    /// Adapt the timeout to the terminal's latency. Defaults to `false`.
+    ///
+    /// Local terminals usually reply in well under a millisecond,
+    /// so the query starts with a short timeout which is extended
+    /// when connected via SSH (`SSH_TTY` or `SSH_CONNECTION`) or when
+    /// earlier queries took long (see [`ThemeModeReport::latency`]).
+    /// [`QueryOptions::timeout`] is used as the upper bound.
+    ///
+    /// If the short timeout elapses for a terminal that answers `DA1`, the timeout is raised to
+    /// [`QueryOptions::timeout`] and the reply is still accepted: such a terminal is slow, not unsupported.
+    /// For other terminals the query fails, but colorsaurus keeps consuming their replies
+    /// up to [`QueryOptions::timeout`] so that they don't end up on the screen.
+    ///
+    /// The latency of the most recent query is remembered for the whole process
+    /// (shared by all threads and callers). Use [`reset_adaptive_timeout`] to forget it,
+    /// e.g. when the program is now talking to a different terminal.
+    pub adaptive_timeout: bool,
+
+    /// How long to keep waiting for the terminal's replies after the timeout elapsed.
+    /// Defaults to zero.
+    ///
+    /// Replies that arrive after the query timed out would otherwise show up as
+    /// garbage such as `^[]11;rgb:...` in the user's shell or in the next program
+    /// reading from the terminal. During the grace period, the terminal is kept in raw mode
+    /// and replies are consumed until the reply to `DA1` arrives.
+    /// The query still fails with [`Error::Timeout`].
+    ///
+    /// Note that input typed by the user during the grace period is consumed as well.
+    pub grace_period: std::time::Duration,
 }
 
 impl Default for QueryOptions {
     fn default() -> Self {
         Self {
             timeout: std::time::Duration::from_secs(1),
+            query_color_scheme: false,
+            string_terminator: quirks::StringTerminatorOption::Auto,
+            adaptive_timeout: false,
+            grace_period: std::time::Duration::ZERO,
         }
     }
 }
 
+/// Forgets the latency measured by earlier queries, which [`QueryOptions::adaptive_timeout`] is based on.
+/// The next query with an adaptive timeout starts with the short timeout again.
+pub fn reset_adaptive_timeout() {
+    imp::reset_adaptive_timeout()
+}
+
 /// Detects if the terminal is dark or light.
 #[doc = include_str!("../doc/caveats.md")]
 #[doc(alias = "theme")]
 pub fn theme_mode(options: QueryOptions) -> Result<ThemeMode> {
-    color_palette(options).map(|p| p.theme_mode())
+    if options.query_color_scheme {
+        theme_mode_report(options).map(|r| r.theme_mode)
+    } else {
+        color_palette(options).map(|p| p.theme_mode())
+    }
+}
+
+/// Detects if the terminal is dark or light and reports how this was determined.
+///
+/// If [`QueryOptions::query_color_scheme`] is enabled, the terminal is also asked
+/// for its color scheme (`CSI ? 996 n`). An explicit answer from the terminal is preferred,
+/// with [`ColorPalette::theme_mode`] as the fallback.
+/// Use [`ThemeModeReport::is_disagreement`] to find out if the two disagree.
+/// The report also contains how long the terminal took to reply ([`ThemeModeReport::latency`]).
+#[doc = include_str!("../doc/caveats.md")]
+pub fn theme_mode_report(options: QueryOptions) -> Result<ThemeModeReport> {
+    imp::theme_mode_report(options)
 }
 
 /// Queries the terminal for it's color palette (foreground and background color).
@@ -181,6 +306,37 @@ pub fn background_color(options: QueryOptions) -> Result<Color> {
     imp::background_color(options)
 }
 
+/// Queries the terminal for its full color palette: foreground, background, cursor,
+/// selection and the 256 indexed colors.
+///
+/// Terminals supporting the [kitty color protocol] (`OSC 21`) such as kitty and Ghostty
+/// report all colors in a single round trip. For all other terminals this falls back
+/// to querying each color individually using `OSC 10`, `OSC 11`, `OSC 12`, `OSC 17`, `OSC 19` and `OSC 4`,
+/// which costs one additional round trip.
+///
+/// If you only need the foreground and background color, use [`color_palette`] instead.
+///
+/// [kitty color protocol]: https://sw.kovidgoyal.net/kitty/color-stack/#setting-and-querying-colors
+#[doc = include_str!("../doc/caveats.md")]
+pub fn extended_color_palette(options: QueryOptions) -> Result<ExtendedColorPalette> {
+    imp::extended_color_palette(options)
+}
+
+/// Queries the terminal for the size of its text area and character cells
+/// using `CSI 14 t`, `CSI 16 t` and `CSI 18 t`.
+///
+/// This is useful for rendering images, since `TIOCGWINSZ`
+/// often reports a size of zero pixels (e.g. when connected via SSH).
+///
+/// Terminals silently ignore the queries they don't support,
+/// so check each field of the returned [`TerminalGeometry`] for availability.
+#[doc = include_str!("../doc/caveats.md")]
+#[doc(alias = "pixel size")]
+#[doc(alias = "cell size")]
+pub fn terminal_geometry(options: QueryOptions) -> Result<TerminalGeometry> {
+    imp::terminal_geometry(options)
+}
+
 #[cfg(test)]
 #[path = "theme_mode_tests.rs"]
 mod tests;
@@ -0,0 +1,153 @@
+//! Process-wide memoization of the terminal's colors.
+//!
+//! Applications often consist of multiple libraries that each want to know the terminal's colors.
+//! Instead of each of them querying the terminal (which requires switching to raw mode and
+//! a round trip to the terminal), the terminal is queried once and the result is shared.
+//!
+//! Concurrent callers are serialized, so that the terminal is never queried twice
+//! at the same time. An unsupported terminal is memoized as well, so that it is not
+//! queried over and over again. Other errors such as timeouts may be transient,
+//! so the terminal is queried again on the next call.
+//!
+//! Call [`refresh`] after a known theme change to query the terminal again.
+//!
+//! ```no_run
+//! use terminal_colorsaurus::{memoized, QueryOptions};
+//!
+//! let theme_mode = memoized::theme_mode(QueryOptions::default());
+//! ```
+
+use crate::{ColorPalette, Error, QueryOptions, Result, ThemeMode};
+use std::sync::{Arc, Mutex};
+
+/// Same as [`color_palette`](crate::color_palette) but only queries the terminal
+/// the first time it's called. All later calls return the same result
+/// unless the query failed with an error other than [`Error::UnsupportedTerminal`].
+///
+/// Note that the `options` are ignored once the palette has been queried.
+pub fn color_palette(options: QueryOptions) -> Result<Arc<ColorPalette>> {
+    PALETTE.get_or_query(|| crate::color_palette(options))
+}
+
+/// Same as [`theme_mode`](crate::theme_mode) but uses the memoized [`color_palette`].
+pub fn theme_mode(options: QueryOptions) -> Result<ThemeMode> {
+    color_palette(options).map(|p| p.theme_mode())
+}
+
+/// Queries the terminal again and replaces the memoized palette with the result.
+pub fn refresh(options: QueryOptions) -> Result<Arc<ColorPalette>> {
+    PALETTE.refresh(|| crate::color_palette(options))
+}
+
+static PALETTE: Memo = Memo::new();
+
+#[derive(Debug)]
+struct Memo(Mutex<Option<Result<Arc<ColorPalette>>>>);
+
+impl Memo {
+    const fn new() -> Self {
+        Self(Mutex::new(None))
+    }
+
+    // The lock is held while querying, so concurrent first callers
+    // wait for the first query to complete instead of querying themselves.
+    fn get_or_query(
+        &self,
+        query: impl FnOnce() -> Result<ColorPalette>,
+    ) -> Result<Arc<ColorPalette>> {
+        let mut value = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(value) = &*value {
+            return share(value);
+        }
+        let result = query().map(Arc::new);
+        // Timeouts and I/O errors might not happen again, so we only memoize permanent errors.
+        if matches!(result, Ok(_) | Err(Error::UnsupportedTerminal(_))) {
+            *value = Some(share(&result));
+        }
+        result
+    }
+
+    fn refresh(&self, query: impl FnOnce() -> Result<ColorPalette>) -> Result<Arc<ColorPalette>> {
+        let mut value = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        *value = None;
+        drop(value);
+        self.get_or_query(query)
+    }
+}
+
+fn share(result: &Result<Arc<ColorPalette>>) -> Result<Arc<ColorPalette>> {
+    match result {
+        Ok(palette) => Ok(Arc::clone(palette)),
+        Err(e) => Err(e.duplicate()),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{Color, ErrorContext, UnsupportedReason};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    fn palette(shade: u16) -> ColorPalette {
+        ColorPalette {
+            foreground: Color::rgb(u16::MAX, u16::MAX, u16::MAX),
+            background: Color::rgb(shade, shade, shade),
+        }
+    }
+
+    #[test]
+    fn queries_once_across_threads() {
+        let memo = Memo::new();
+        let queries = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    memo.get_or_query(|| {
+                        queries.fetch_add(1, Ordering::SeqCst);
+                        Ok(palette(0))
+                    })
+                    .unwrap()
+                });
+            }
+        });
+        assert_eq!(1, queries.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn memoizes_unsupported_terminal() {
+        let memo = Memo::new();
+        assert!(memo
+            .get_or_query(|| Err(Error::unsupported(UnsupportedReason::Quirks)))
+            .is_err());
+        let result = memo.get_or_query(|| Ok(palette(0)));
+        assert!(matches!(result, Err(Error::UnsupportedTerminal(_))));
+    }
+
+    #[test]
+    fn retries_after_transient_errors() {
+        let memo = Memo::new();
+        let timeout = || {
+            Err(Error::timeout(
+                Duration::from_secs(1),
+                ErrorContext::default(),
+            ))
+        };
+        assert!(matches!(memo.get_or_query(timeout), Err(Error::Timeout(_))));
+        let io_error = || Err(std::io::Error::other("oops").into());
+        assert!(matches!(memo.get_or_query(io_error), Err(Error::Io(_))));
+        assert_eq!(palette(0), *memo.get_or_query(|| Ok(palette(0))).unwrap());
+        assert_eq!(palette(0), *memo.get_or_query(|| Ok(palette(1))).unwrap());
+    }
+
+    #[test]
+    fn refresh_queries_again() {
+        let memo = Memo::new();
+        assert_eq!(palette(0), *memo.get_or_query(|| Ok(palette(0))).unwrap());
+        assert_eq!(palette(0), *memo.get_or_query(|| Ok(palette(1))).unwrap());
+        assert_eq!(palette(1), *memo.refresh(|| Ok(palette(1))).unwrap());
+        assert_eq!(palette(1), *memo.get_or_query(|| Ok(palette(2))).unwrap());
+    }
+}
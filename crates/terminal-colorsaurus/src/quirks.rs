@@ -1,97 +1,575 @@
-use std::env;
+//! Terminal-specific workarounds.
+//!
+//! Colorsaurus decides how to talk to the terminal based on environment variables
+//! such as `TERM`, `TERM_PROGRAM` or `VTE_VERSION`. The decision is driven by a table of
+//! [`QuirksEntry`]s where the first entry whose [`EnvMatcher`]s all match wins.
+//!
+//! The built-in table can be extended (or overridden) at runtime using [`register`].
+//! This allows apps to ship fixes for misbehaving terminals without waiting for a release.
+//!
+//! ```
+//! use terminal_colorsaurus::quirks::{self, EnvMatcher, QuirksEntry, TerminalQuirks};
+//!
+//! let mut unsupported = TerminalQuirks::default();
+//! unsupported.unsupported = true;
+//! quirks::register(QuirksEntry::new(
+//!     vec![EnvMatcher::equals("TERM_PROGRAM", "BrokenTerm")],
+//!     unsupported,
+//! ));
+//! ```
+
+use crate::trace;
+use std::borrow::Cow;
 use std::io::{self, Write};
-use std::sync::OnceLock;
-
-pub(crate) fn terminal_quirks_from_env() -> TerminalQuirks {
-    // This OnceLock is not here for efficiency, it's here so that
-    // we have consistent results in case a consumer uses `set_var`.
-    static TERMINAL_QUIRK: OnceLock<TerminalQuirks> = OnceLock::new();
-    *TERMINAL_QUIRK.get_or_init(terminal_quirk_from_env_eager)
-}
-
-fn terminal_quirk_from_env_eager() -> TerminalQuirks {
-    use TerminalQuirks::*;
-    match env::var("TERM") {
-        // Something is very wrong if we don't have a TERM env var
-        // or if it's not valid unicode.
-        Err(env::VarError::NotUnicode(_)) => Unsupported,
-        // Something is very wrong if we don't have a TERM env var.
-        #[cfg(unix)]
-        Err(env::VarError::NotPresent) => Unsupported,
-        // On Windows the TERM convention is not universally followed.
-        #[cfg(not(unix))]
-        Err(env::VarError::NotPresent) => None,
-        // `TERM=dumb` indicates that the terminal supports very little features.
-        // We don't want to send any escape sequences to those terminals.
-        Ok(term) if term == "dumb" => Unsupported,
-        // Why is GNU Screen unsupported?
-        //
-        // Note: The following only applies if screen was compiled with `--enable-rxvt_osc`.
-        //       Homebrew is a notable packager who doesn't enable this feature.
-        //
-        // 1. Screen only supports `OSC 11` (background) and not `OSC 10` (foreground)
-        //
-        // 2. Screen replies to queries in the incorrect order.
-        //    We send  `OSC 11` + `DA1` and expect the answers to also be in that order.
-        //    However, as far as I can tell, Screen relays the `OSC 11` query to the underlying terminal,
-        //    and so we get the `DA1` response back *first*. This is usually an indicator that
-        //    the terminal doesn't support the `OSC` query.
-        //
-        //    There are two both equally broken workarounds:
-        //
-        //    * Don't send `DA1`, just `OSC 11`. \
-        //      Since Screen forwards the query to the underlying terminal, we won't get an answer
-        //      if the underlying terminal doesn't support it. And we don't have a way to detect that
-        //      => we hit the 1s timeout :/
-        //
-        //    * Send the query (`OSC 11` + `DA1`) to the underlying terminal by wrapping it between `CSI P` and `ST`.
-        //      (There's a reverted commit that does exactly this: f06206b53d2499e95627ef29e5e35278209725db)
-        //      * If there's exactly one attached display (underlying terminal)
-        //        => everything works as usual.
-        //      * If there's no attached display we don't get an answer to DA1
-        //        => we hit the 1s timeout :/
-        //      * If there are multiple displays attached (yes this is supported and quite fun to try) we get back multiple responses
-        //        => since there's no way to know that we need to expect multiple responses
-        //           some of them are not consumed by us and end up on the user's screen :/
-        Ok(term) if term == "screen" || term.starts_with("screen.") => Unsupported,
-        // Eterm doesn't even support `DA1`, so we list it here to avoid running into the timeout.
-        Ok(term) if term == "Eterm" => Unsupported,
-        Ok(_) => None,
-    }
-}
-
-#[derive(Debug, Copy, Clone)]
-pub(crate) enum TerminalQuirks {
-    None,
-    Unsupported,
+use std::sync::{Mutex, MutexGuard};
+use std::{env, error, fmt};
+
+/// Describes how to talk to a terminal.
+/// Determined from the environment using the quirks table, see [`terminal_quirks`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct TerminalQuirks {
+    /// The terminal does not support querying for its colors.
+    /// No escape sequences are sent to the terminal at all.
+    pub unsupported: bool,
+    /// The string terminator used to terminate `OSC` queries.
+    pub string_terminator: StringTerminator,
+    /// Queries need to be wrapped to pass through a terminal multiplexer
+    /// to the underlying terminal.
+    pub passthrough: Option<Passthrough>,
+    /// The terminal answers `DA1`. If it doesn't, colorsaurus can't detect
+    /// unsupported queries early and has to wait for the timeout instead.
+    pub answers_da1: bool,
+}
+
+impl Default for TerminalQuirks {
+    fn default() -> Self {
+        Self {
+            unsupported: false,
+            // The currently released version of rxvt-unicode (urxvt) has a bug where it terminates the response with `ESC` instead of `ST` (`ESC \`).
+            // This causes us to run into the timeout because we get stuck waiting for a `\` that never arrives.
+            // Fixed by revision [1.600](http://cvs.schmorp.de/rxvt-unicode/src/command.C?revision=1.600&view=markup).
+            // The bug can be worked around by sending a query with `BEL` which will result in a `BEL`-terminated response.
+            //
+            // Originally, we used `BEL` only for urxvt. However, after a discussion in delta [1],
+            // I noticed that there are quite a few people who use urxvt with a different `TERM`
+            // env var (e.g. `urxvt`, `xterm`, or even `screen`) [2].
+            //
+            // [1]: https://github.com/dandavison/delta/issues/1897
+            // [2]: https://github.com/search?q=URxvt*termName&type=code
+            string_terminator: StringTerminator::Bel,
+            passthrough: None,
+            answers_da1: true,
+        }
+    }
+}
+
+/// The control character used to terminate `OSC` sequences.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[allow(clippy::exhaustive_enums)]
+pub enum StringTerminator {
+    /// `BEL` (`0x07`)
+    Bel,
+    /// `ST` (`ESC \`)
+    St,
+}
+
+impl StringTerminator {
+    /// The terminator's bytes, for writing your own `OSC` sequences.
+    pub fn as_bytes(self) -> &'static [u8] {
+        match self {
+            StringTerminator::Bel => b"\x07",
+            StringTerminator::St => b"\x1b\\",
+        }
+    }
+}
+
+/// How to choose the string terminator, see [`QueryOptions::string_terminator`](crate::QueryOptions::string_terminator).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[allow(clippy::exhaustive_enums)]
+pub enum StringTerminatorOption {
+    /// Use the terminator from the terminal's quirks, see [`TerminalQuirks::string_terminator`].
+    #[default]
+    Auto,
+    /// Always use `BEL` (`0x07`).
+    Bel,
+    /// Always use `ST` (`ESC \`), except with [`Passthrough::Screen`] (see there).
+    St,
+}
+
+impl StringTerminatorOption {
+    pub(crate) fn resolve(self, quirks: TerminalQuirks) -> StringTerminator {
+        match self {
+            StringTerminatorOption::Auto => quirks.string_terminator,
+            StringTerminatorOption::Bel => StringTerminator::Bel,
+            StringTerminatorOption::St if quirks.passthrough == Some(Passthrough::Screen) => {
+                StringTerminator::Bel
+            }
+            StringTerminatorOption::St => StringTerminator::St,
+        }
+    }
+}
+
+/// A terminal multiplexer that needs queries to be wrapped
+/// in order to reach the underlying terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Passthrough {
+    /// tmux: `ESC P tmux; <query with ESC doubled> ESC \`.
+    /// Requires the `allow-passthrough` option to be enabled.
+    Tmux,
+    /// GNU Screen: `ESC P <query> ESC \`.
+    ///
+    /// Queries are always terminated with `BEL`, since the `ST` of the query
+    /// would end the passthrough early and the rest would end up on the screen.
+    Screen,
+}
+
+/// An entry in the quirks table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuirksEntry {
+    matchers: Vec<EnvMatcher>,
+    quirks: TerminalQuirks,
+}
+
+impl QuirksEntry {
+    /// Creates an entry that applies `quirks` if *all* of the `matchers` match.
+    pub fn new(matchers: Vec<EnvMatcher>, quirks: TerminalQuirks) -> Self {
+        Self { matchers, quirks }
+    }
+
+    fn matches(&self, env: &dyn Fn(&str) -> Result<String, env::VarError>) -> bool {
+        self.matchers.iter().all(|m| m.matches(env))
+    }
+}
+
+/// Matches the value of an environment variable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvMatcher {
+    var: Cow<'static, str>,
+    kind: EnvMatcherKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum EnvMatcherKind {
+    Equals(Cow<'static, str>),
+    StartsWith(Cow<'static, str>),
+    Present,
+    Missing,
+    NotUnicode,
+    VersionAtLeast(Vec<u64>),
+    VersionBelow(Vec<u64>),
+}
+
+impl EnvMatcher {
+    fn new(var: impl Into<Cow<'static, str>>, kind: EnvMatcherKind) -> Self {
+        Self {
+            var: var.into(),
+            kind,
+        }
+    }
+
+    /// Matches if the variable is set to exactly `value`.
+    pub fn equals(var: impl Into<Cow<'static, str>>, value: impl Into<Cow<'static, str>>) -> Self {
+        Self::new(var, EnvMatcherKind::Equals(value.into()))
+    }
+
+    /// Matches if the variable's value starts with `prefix`.
+    pub fn starts_with(
+        var: impl Into<Cow<'static, str>>,
+        prefix: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        Self::new(var, EnvMatcherKind::StartsWith(prefix.into()))
+    }
+
+    /// Matches if the variable is set, regardless of its value (e.g. `KITTY_WINDOW_ID` or `WT_SESSION`).
+    pub fn present(var: impl Into<Cow<'static, str>>) -> Self {
+        Self::new(var, EnvMatcherKind::Present)
+    }
+
+    /// Matches if the variable is not set.
+    pub fn missing(var: impl Into<Cow<'static, str>>) -> Self {
+        Self::new(var, EnvMatcherKind::Missing)
+    }
+
+    /// Matches if the variable contains a version (e.g. `3.5.1` or `7600`)
+    /// that is greater than or equal to `version`.
+    ///
+    /// Fails if `version` is not made up of numbers separated by dots.
+    pub fn version_at_least(
+        var: impl Into<Cow<'static, str>>,
+        version: &str,
+    ) -> Result<Self, InvalidVersionError> {
+        let version = parse_version(version)?;
+        Ok(Self::new(var, EnvMatcherKind::VersionAtLeast(version)))
+    }
+
+    /// Matches if the variable contains a version (e.g. `3.5.1` or `7600`)
+    /// that is less than `version`.
+    ///
+    /// Fails if `version` is not made up of numbers separated by dots.
+    pub fn version_below(
+        var: impl Into<Cow<'static, str>>,
+        version: &str,
+    ) -> Result<Self, InvalidVersionError> {
+        let version = parse_version(version)?;
+        Ok(Self::new(var, EnvMatcherKind::VersionBelow(version)))
+    }
+
+    fn matches(&self, env: &dyn Fn(&str) -> Result<String, env::VarError>) -> bool {
+        use EnvMatcherKind::*;
+        let value = env(&self.var);
+        match (&self.kind, value) {
+            (Equals(expected), Ok(value)) => value == *expected,
+            (StartsWith(prefix), Ok(value)) => value.starts_with(prefix.as_ref()),
+            (Present, Ok(_) | Err(env::VarError::NotUnicode(_))) => true,
+            (Missing, Err(env::VarError::NotPresent)) => true,
+            (NotUnicode, Err(env::VarError::NotUnicode(_))) => true,
+            (VersionAtLeast(min), Ok(value)) => {
+                try_parse_version(&value).is_some_and(|v| v >= *min)
+            }
+            (VersionBelow(max), Ok(value)) => try_parse_version(&value).is_some_and(|v| v < *max),
+            _ => false,
+        }
+    }
+}
+
+fn parse_version(version: &str) -> Result<Vec<u64>, InvalidVersionError> {
+    try_parse_version(version).ok_or_else(|| InvalidVersionError {
+        version: version.to_owned(),
+    })
+}
+
+fn try_parse_version(version: &str) -> Option<Vec<u64>> {
+    version.split('.').map(|part| part.parse().ok()).collect()
+}
+
+/// The error returned by [`EnvMatcher::version_at_least`] and [`EnvMatcher::version_below`]
+/// when the version to compare against is invalid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct InvalidVersionError {
+    /// The invalid version.
+    pub version: String,
+}
+
+impl error::Error for InvalidVersionError {}
+
+impl fmt::Display for InvalidVersionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid version '{}', expected numbers separated by dots (e.g. '3.5.1')",
+            self.version
+        )
+    }
+}
+
+/// Adds an entry to the quirks table.
+///
+/// Registered entries take precedence over the built-in entries,
+/// entries registered later take precedence over entries registered earlier.
+/// The environment is evaluated again on the next query.
+pub fn register(entry: QuirksEntry) {
+    let mut state = state();
+    state.registered.insert(0, entry);
+    state.cached = None;
+}
+
+/// Returns the quirks that apply to the current terminal.
+///
+/// The result is cached so that we have consistent results
+/// in case a consumer uses `set_var`. Calling [`register`] clears this cache.
+pub fn terminal_quirks() -> TerminalQuirks {
+    let mut state = state();
+    if let Some(quirks) = state.cached {
+        return quirks;
+    }
+    let quirks = quirks_for_env(&state.registered, &|var| env::var(var));
+    state.cached = Some(quirks);
+    quirks
+}
+
+struct State {
+    registered: Vec<QuirksEntry>,
+    cached: Option<TerminalQuirks>,
+}
+
+fn state() -> MutexGuard<'static, State> {
+    static STATE: Mutex<State> = Mutex::new(State {
+        registered: Vec::new(),
+        cached: None,
+    });
+    STATE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn quirks_for_env(
+    registered: &[QuirksEntry],
+    env: &dyn Fn(&str) -> Result<String, env::VarError>,
+) -> TerminalQuirks {
+    let entry = registered
+        .iter()
+        .chain(builtin_entries().iter())
+        .find(|entry| entry.matches(env));
+    trace::event!(debug, ?entry, "matched terminal quirks");
+    let mut quirks = entry.map(|entry| entry.quirks).unwrap_or_default();
+    if quirks.passthrough == Some(Passthrough::Screen) {
+        quirks.string_terminator = StringTerminator::Bel;
+    }
+    quirks
+}
+
+fn builtin_entries() -> &'static [QuirksEntry] {
+    static BUILTIN: std::sync::OnceLock<Vec<QuirksEntry>> = std::sync::OnceLock::new();
+    BUILTIN.get_or_init(|| {
+        let unsupported = TerminalQuirks {
+            unsupported: true,
+            ..Default::default()
+        };
+        let st = TerminalQuirks {
+            string_terminator: StringTerminator::St,
+            ..Default::default()
+        };
+        vec![
+            // Something is very wrong if we don't have a TERM env var
+            // or if it's not valid unicode.
+            QuirksEntry::new(
+                vec![EnvMatcher::new("TERM", EnvMatcherKind::NotUnicode)],
+                unsupported,
+            ),
+            // Something is very wrong if we don't have a TERM env var.
+            // On Windows the TERM convention is not universally followed.
+            #[cfg(unix)]
+            QuirksEntry::new(vec![EnvMatcher::missing("TERM")], unsupported),
+            // `TERM=dumb` indicates that the terminal supports very little features.
+            // We don't want to send any escape sequences to those terminals.
+            QuirksEntry::new(vec![EnvMatcher::equals("TERM", "dumb")], unsupported),
+            // Why is GNU Screen unsupported?
+            //
+            // Note: The following only applies if screen was compiled with `--enable-rxvt_osc`.
+            //       Homebrew is a notable packager who doesn't enable this feature.
+            //
+            // 1. Screen only supports `OSC 11` (background) and not `OSC 10` (foreground)
+            //
+            // 2. Screen replies to queries in the incorrect order.
+            //    We send  `OSC 11` + `DA1` and expect the answers to also be in that order.
+            //    However, as far as I can tell, Screen relays the `OSC 11` query to the underlying terminal,
+            //    and so we get the `DA1` response back *first*. This is usually an indicator that
+            //    the terminal doesn't support the `OSC` query.
+            //
+            //    There are two both equally broken workarounds:
+            //
+            //    * Don't send `DA1`, just `OSC 11`. \
+            //      Since Screen forwards the query to the underlying terminal, we won't get an answer
+            //      if the underlying terminal doesn't support it. And we don't have a way to detect that
+            //      => we hit the 1s timeout :/
+            //
+            //    * Send the query (`OSC 11` + `DA1`) to the underlying terminal by wrapping it between `CSI P` and `ST`.
+            //      (There's a reverted commit that does exactly this: f06206b53d2499e95627ef29e5e35278209725db)
+            //      * If there's exactly one attached display (underlying terminal)
+            //        => everything works as usual.
+            //      * If there's no attached display we don't get an answer to DA1
+            //        => we hit the 1s timeout :/
+            //      * If there are multiple displays attached (yes this is supported and quite fun to try) we get back multiple responses
+            //        => since there's no way to know that we need to expect multiple responses
+            //           some of them are not consumed by us and end up on the user's screen :/
+            QuirksEntry::new(vec![EnvMatcher::equals("TERM", "screen")], unsupported),
+            QuirksEntry::new(
+                vec![EnvMatcher::starts_with("TERM", "screen.")],
+                unsupported,
+            ),
+            // Eterm doesn't even support `DA1`, so we list it here to avoid running into the timeout.
+            QuirksEntry::new(
+                vec![EnvMatcher::equals("TERM", "Eterm")],
+                TerminalQuirks {
+                    answers_da1: false,
+                    ..unsupported
+                },
+            ),
+            // These terminals handle `ST`-terminated queries correctly. We match on `TERM`
+            // rather than on `TERM_PROGRAM` since the latter is inherited by terminals
+            // started from within another terminal (e.g. urxvt started from kitty).
+            QuirksEntry::new(vec![EnvMatcher::equals("TERM", "xterm-kitty")], st),
+            QuirksEntry::new(vec![EnvMatcher::equals("TERM", "xterm-ghostty")], st),
+            QuirksEntry::new(vec![EnvMatcher::equals("TERM", "wezterm")], st),
+            QuirksEntry::new(vec![EnvMatcher::equals("TERM", "alacritty")], st),
+            QuirksEntry::new(vec![EnvMatcher::starts_with("TERM", "foot")], st),
+        ]
+    })
 }
 
 impl TerminalQuirks {
     pub(crate) fn is_known_unsupported(self) -> bool {
-        matches!(self, TerminalQuirks::Unsupported)
+        self.unsupported
     }
 
     pub(crate) fn string_terminator(self) -> &'static [u8] {
-        // The currently released version of rxvt-unicode (urxvt) has a bug where it terminates the response with `ESC` instead of `ST` (`ESC \`).
-        // This causes us to run into the timeout because we get stuck waiting for a `\` that never arrives.
-        // Fixed by revision [1.600](http://cvs.schmorp.de/rxvt-unicode/src/command.C?revision=1.600&view=markup).
-        // The bug can be worked around by sending a query with `BEL` which will result in a `BEL`-terminated response.
-        //
-        // Originally, we used `BEL` only for urxvt. However, after a discussion in delta [1],
-        // I noticed that there are quite a few people who use urxvt with a different `TERM`
-        // env var (e.g. `urxvt`, `xterm`, or even `screen`) [2].
-        //
-        // [1]: https://github.com/dandavison/delta/issues/1897
-        // [2]: https://github.com/search?q=URxvt*termName&type=code
-        const BEL: u8 = 0x07;
-        &[BEL]
-    }
-
-    pub(crate) fn write_all(self, w: &mut dyn Write, bytes: &[u8]) -> io::Result<()> {
-        w.write_all(bytes)
-    }
-
-    pub(crate) fn write_string_terminator(self, writer: &mut dyn Write) -> io::Result<()> {
-        self.write_all(writer, self.string_terminator())
+        self.string_terminator.as_bytes()
+    }
+
+    /// Writes an escape sequence, wrapping it for [passthrough](Self::passthrough) if needed.
+    ///
+    /// Together with the string terminator, this allows sending your own
+    /// sequences (e.g. for setting colors) the same way colorsaurus sends its queries.
+    pub fn write_all(self, w: &mut dyn Write, bytes: &[u8]) -> io::Result<()> {
+        match self.passthrough {
+            None => w.write_all(bytes),
+            Some(Passthrough::Tmux) => {
+                w.write_all(b"\x1bPtmux;")?;
+                for chunk in bytes.split_inclusive(|b| *b == 0x1b) {
+                    w.write_all(chunk)?;
+                    if chunk.ends_with(b"\x1b") {
+                        w.write_all(b"\x1b")?;
+                    }
+                }
+                w.write_all(b"\x1b\\")
+            }
+            Some(Passthrough::Screen) => {
+                w.write_all(b"\x1bP")?;
+                w.write_all(bytes)?;
+                w.write_all(b"\x1b\\")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn env_from<'a>(
+        vars: &'a [(&'a str, &'a str)],
+    ) -> impl Fn(&str) -> Result<String, env::VarError> + 'a {
+        move |name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| (*value).to_owned())
+                .ok_or(env::VarError::NotPresent)
+        }
+    }
+
+    #[test]
+    fn dumb_and_screen_are_unsupported() {
+        for term in ["dumb", "screen", "screen.xterm-256color", "Eterm"] {
+            let quirks = quirks_for_env(&[], &env_from(&[("TERM", term)]));
+            assert!(quirks.unsupported, "{term}");
+        }
+    }
+
+    #[test]
+    fn unknown_terminal_uses_default_quirks() {
+        let quirks = quirks_for_env(&[], &env_from(&[("TERM", "xterm-256color")]));
+        assert_eq!(TerminalQuirks::default(), quirks);
+    }
+
+    #[test]
+    fn picks_string_terminator_per_terminal() {
+        let quirks = quirks_for_env(&[], &env_from(&[("TERM", "xterm-kitty")]));
+        assert_eq!(StringTerminator::St, quirks.string_terminator);
+        let env = [
+            ("TERM", "rxvt-unicode-256color"),
+            ("TERM_PROGRAM", "WezTerm"),
+        ];
+        let quirks = quirks_for_env(&[], &env_from(&env));
+        assert_eq!(StringTerminator::Bel, quirks.string_terminator);
+    }
+
+    #[test]
+    fn string_terminator_option_overrides_quirks() {
+        let kitty = quirks_for_env(&[], &env_from(&[("TERM", "xterm-kitty")]));
+        assert_eq!(
+            StringTerminator::St,
+            StringTerminatorOption::Auto.resolve(kitty)
+        );
+        assert_eq!(
+            StringTerminator::Bel,
+            StringTerminatorOption::Bel.resolve(kitty)
+        );
+        let xterm = TerminalQuirks::default();
+        assert_eq!(
+            StringTerminator::Bel,
+            StringTerminatorOption::Auto.resolve(xterm)
+        );
+        assert_eq!(
+            StringTerminator::St,
+            StringTerminatorOption::St.resolve(xterm)
+        );
+    }
+
+    #[test]
+    fn registered_entries_take_precedence() {
+        let quirks = TerminalQuirks {
+            string_terminator: StringTerminator::St,
+            ..Default::default()
+        };
+        let registered = [QuirksEntry::new(
+            vec![
+                EnvMatcher::equals("TERM", "screen"),
+                EnvMatcher::present("FIXED_SCREEN"),
+            ],
+            quirks,
+        )];
+        let env = [("TERM", "screen"), ("FIXED_SCREEN", "1")];
+        assert_eq!(quirks, quirks_for_env(&registered, &env_from(&env)));
+        let env = [("TERM", "screen")];
+        assert!(quirks_for_env(&registered, &env_from(&env)).unsupported);
+    }
+
+    #[test]
+    fn matches_versions() {
+        let matcher = EnvMatcher::version_at_least("TERM_PROGRAM_VERSION", "3.4").unwrap();
+        assert!(matcher.matches(&env_from(&[("TERM_PROGRAM_VERSION", "3.4")])));
+        assert!(matcher.matches(&env_from(&[("TERM_PROGRAM_VERSION", "3.10.1")])));
+        assert!(!matcher.matches(&env_from(&[("TERM_PROGRAM_VERSION", "3.3.9")])));
+        assert!(!matcher.matches(&env_from(&[("TERM_PROGRAM_VERSION", "next")])));
+        assert!(!matcher.matches(&env_from(&[])));
+
+        let matcher = EnvMatcher::version_below("VTE_VERSION", "6800").unwrap();
+        assert!(matcher.matches(&env_from(&[("VTE_VERSION", "5202")])));
+        assert!(!matcher.matches(&env_from(&[("VTE_VERSION", "7600")])));
+    }
+
+    #[test]
+    fn rejects_invalid_versions() {
+        for version in ["garbage", "", "3..4", "v3.4"] {
+            let error = EnvMatcher::version_at_least("VTE_VERSION", version).unwrap_err();
+            assert_eq!(version, error.version);
+            assert!(EnvMatcher::version_below("VTE_VERSION", version).is_err());
+        }
+    }
+
+    #[test]
+    fn terminates_queries_with_bel_for_screen_passthrough() {
+        let registered = [QuirksEntry::new(
+            vec![EnvMatcher::present("STY")],
+            TerminalQuirks {
+                string_terminator: StringTerminator::St,
+                passthrough: Some(Passthrough::Screen),
+                ..Default::default()
+            },
+        )];
+        let quirks = quirks_for_env(&registered, &env_from(&[("STY", "1.pts-0")]));
+        assert_eq!(StringTerminator::Bel, quirks.string_terminator);
+        assert_eq!(
+            StringTerminator::Bel,
+            StringTerminatorOption::St.resolve(quirks)
+        );
+
+        let mut buf = Vec::new();
+        let query = [&b"\x1b]11;?"[..], quirks.string_terminator.as_bytes()].concat();
+        quirks.write_all(&mut buf, &query).unwrap();
+        assert_eq!(b"\x1bP\x1b]11;?\x07\x1b\\".as_slice(), buf);
+    }
+
+    #[test]
+    fn wraps_queries_for_tmux() {
+        let quirks = TerminalQuirks {
+            passthrough: Some(Passthrough::Tmux),
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        quirks.write_all(&mut buf, b"\x1b]11;?\x07").unwrap();
+        assert_eq!(b"\x1bPtmux;\x1b\x1b]11;?\x07\x1b\\".as_slice(), buf);
     }
 }
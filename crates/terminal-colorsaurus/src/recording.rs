@@ -0,0 +1,294 @@
+//! Recording and replaying the exchange with the terminal.
+//!
+//! A [`Recording`] captures the exact bytes sent to the terminal and the terminal's replies
+//! (including when they arrived). Recordings are stored as plain text fixtures
+//! which can be replayed through the same parsing code without a terminal,
+//! turning the behaviour of real terminals into deterministic regression tests.
+//!
+//! Record a fixture by running the `record` example in the terminal of interest:
+//! ```shell
+//! cargo run --example record > tests/fixtures/my-terminal.txt
+//! ```
+//!
+//! ```
+//! use terminal_colorsaurus::recording::{self, Recording};
+//!
+//! let fixture = "\
+//! query=\\e]10;?\\a\\e]11;?\\a\\e[c
+//! reply=0.000120 \\e]10;rgb:ffff/ffff/ffff\\a\\e]11;rgb:0000/0000/0000\\a
+//! reply=0.000150 \\e[?62;22c
+//! ";
+//! let recording = Recording::parse(fixture).unwrap();
+//! let palette = recording::replay_color_palette(&recording).unwrap();
+//! assert_eq!(terminal_colorsaurus::ThemeMode::Dark, palette.theme_mode());
+//! ```
+
+use crate::quirks::{Passthrough, StringTerminator, TerminalQuirks};
+use crate::{imp, ColorPalette, ExtendedColorPalette, QueryOptions, Result, ThemeModeReport};
+use core::fmt;
+use std::time::Duration;
+
+/// An exchange with the terminal.
+/// Created by [`record_color_palette`] or parsed from a fixture using [`Recording::parse`].
+///
+/// The [`Display`](fmt::Display) implementation produces the fixture format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Recording {
+    /// The quirks that were in effect when recording.
+    pub quirks: TerminalQuirks,
+    /// The timeout used when recording. Defaults to 1 s.
+    pub timeout: Duration,
+    /// Everything that was sent to the terminal.
+    pub query: Vec<u8>,
+    /// The terminal's replies in the order they arrived.
+    pub replies: Vec<RecordedReply>,
+}
+
+impl Default for Recording {
+    fn default() -> Self {
+        Self {
+            quirks: TerminalQuirks::default(),
+            timeout: QueryOptions::default().timeout,
+            query: Vec::new(),
+            replies: Vec::new(),
+        }
+    }
+}
+
+/// A chunk of bytes received from the terminal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RecordedReply {
+    /// The time between the first attempt to read a reply and the arrival of this chunk.
+    pub elapsed: Duration,
+    /// The received bytes.
+    pub bytes: Vec<u8>,
+}
+
+impl RecordedReply {
+    /// Creates a reply that arrived `elapsed` after we started reading.
+    pub fn new(elapsed: Duration, bytes: impl Into<Vec<u8>>) -> Self {
+        Self {
+            elapsed,
+            bytes: bytes.into(),
+        }
+    }
+}
+
+/// Same as [`color_palette`](crate::color_palette) but also records the exchange with the terminal.
+pub fn record_color_palette(options: QueryOptions) -> (Result<ColorPalette>, Recording) {
+    imp::record_color_palette(options)
+}
+
+/// Same as [`theme_mode_report`](crate::theme_mode_report) but also records the exchange with the terminal.
+///
+/// Unlike [`theme_mode_report`](crate::theme_mode_report), this always queries the terminal
+/// and leaves the [cache](crate::cache) alone.
+pub fn record_theme_mode_report(options: QueryOptions) -> (Result<ThemeModeReport>, Recording) {
+    imp::record_theme_mode_report(options)
+}
+
+/// Same as [`extended_color_palette`](crate::extended_color_palette) but also records the exchange with the terminal.
+///
+/// Terminals that don't support the kitty color protocol are queried a second time for each color individually.
+/// The recording then contains both round trips.
+pub fn record_extended_color_palette(
+    options: QueryOptions,
+) -> (Result<ExtendedColorPalette>, Recording) {
+    imp::record_extended_color_palette(options)
+}
+
+/// Replays a recorded exchange through the same code that [`color_palette`](crate::color_palette) uses.
+/// This does not talk to the terminal.
+///
+/// Time is simulated, so replaying is instant: replies that arrived after
+/// the recorded timeout result in [`Error::Timeout`](crate::Error::Timeout).
+/// Fails with an I/O error if the query differs from the recorded query.
+pub fn replay_color_palette(recording: &Recording) -> Result<ColorPalette> {
+    imp::replay_color_palette(recording)
+}
+
+impl Recording {
+    /// Parses a recording from the fixture format.
+    /// Empty lines and lines starting with `#` are ignored.
+    ///
+    /// Returns [`None`] if the fixture is malformed.
+    pub fn parse(fixture: &str) -> Option<Self> {
+        let mut recording = Recording::default();
+        let lines = fixture
+            .lines()
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        for line in lines {
+            let (key, value) = line.split_once('=')?;
+            match key {
+                "unsupported" => recording.quirks.unsupported = parse_bool(value)?,
+                "string_terminator" => {
+                    recording.quirks.string_terminator = match value {
+                        "bel" => StringTerminator::Bel,
+                        "st" => StringTerminator::St,
+                        _ => return None,
+                    }
+                }
+                "passthrough" => {
+                    recording.quirks.passthrough = match value {
+                        "none" => None,
+                        "tmux" => Some(Passthrough::Tmux),
+                        "screen" => Some(Passthrough::Screen),
+                        _ => return None,
+                    }
+                }
+                "answers_da1" => recording.quirks.answers_da1 = parse_bool(value)?,
+                "timeout" => recording.timeout = parse_duration(value)?,
+                "query" => recording.query = unescape(value)?,
+                "reply" => {
+                    let (elapsed, bytes) = value.split_once(' ')?;
+                    recording.replies.push(RecordedReply::new(
+                        parse_duration(elapsed)?,
+                        unescape(bytes)?,
+                    ));
+                }
+                _ => return None,
+            }
+        }
+        Some(recording)
+    }
+}
+
+impl fmt::Display for Recording {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let string_terminator = match self.quirks.string_terminator {
+            StringTerminator::Bel => "bel",
+            StringTerminator::St => "st",
+        };
+        let passthrough = match self.quirks.passthrough {
+            None => "none",
+            Some(Passthrough::Tmux) => "tmux",
+            Some(Passthrough::Screen) => "screen",
+        };
+        writeln!(f, "unsupported={}", self.quirks.unsupported)?;
+        writeln!(f, "string_terminator={string_terminator}")?;
+        writeln!(f, "passthrough={passthrough}")?;
+        writeln!(f, "answers_da1={}", self.quirks.answers_da1)?;
+        writeln!(f, "timeout={:.6}", self.timeout.as_secs_f64())?;
+        writeln!(f, "query={}", Escape(&self.query))?;
+        for reply in &self.replies {
+            writeln!(
+                f,
+                "reply={:.6} {}",
+                reply.elapsed.as_secs_f64(),
+                Escape(&reply.bytes)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_bool(input: &str) -> Option<bool> {
+    input.parse().ok()
+}
+
+fn parse_duration(input: &str) -> Option<Duration> {
+    Duration::try_from_secs_f64(input.parse().ok()?).ok()
+}
+
+// Escapes bytes such that a fixture is a readable text file with one exchange per line.
+struct Escape<'a>(&'a [u8]);
+
+impl fmt::Display for Escape<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            match byte {
+                0x1b => f.write_str("\\e")?,
+                0x07 => f.write_str("\\a")?,
+                b'\\' => f.write_str("\\\\")?,
+                0x20..=0x7e => write!(f, "{}", char::from(*byte))?,
+                _ => write!(f, "\\x{byte:02x}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn unescape(input: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(input.len());
+    let mut rest = input.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        let (&escape, tail) = rest.split_first()?;
+        rest = tail;
+        match escape {
+            b'e' => bytes.push(0x1b),
+            b'a' => bytes.push(0x07),
+            b'\\' => bytes.push(b'\\'),
+            b'x' => {
+                let hex = rest.get(..2)?;
+                bytes.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+                rest = &rest[2..];
+            }
+            _ => return None,
+        }
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_round_trip() {
+        let bytes = b"\x1b]11;rgb:0000/0000/0000\x1b\\\x07\x00\xff a=b";
+        let escaped = Escape(bytes).to_string();
+        assert_eq!("\\e]11;rgb:0000/0000/0000\\e\\\\\\a\\x00\\xff a=b", escaped);
+        assert_eq!(Some(bytes.to_vec()), unescape(&escaped));
+    }
+
+    #[test]
+    fn rejects_invalid_escapes() {
+        assert_eq!(None, unescape("\\"));
+        assert_eq!(None, unescape("\\q"));
+        assert_eq!(None, unescape("\\x1"));
+        assert_eq!(None, unescape("\\xzz"));
+    }
+
+    #[test]
+    fn fixture_round_trip() {
+        let recording = Recording {
+            quirks: TerminalQuirks {
+                string_terminator: StringTerminator::St,
+                passthrough: Some(Passthrough::Tmux),
+                ..Default::default()
+            },
+            timeout: Duration::from_millis(500),
+            query: b"\x1b]10;?\x1b\\\x1b[c".to_vec(),
+            replies: vec![
+                RecordedReply::new(Duration::from_micros(120), "\x1b]10;rgb:ffff/ffff/ffff\x07"),
+                RecordedReply::new(Duration::from_micros(150), "\x1b[?62;22c"),
+            ],
+        };
+        assert_eq!(
+            Some(recording.clone()),
+            Recording::parse(&recording.to_string())
+        );
+    }
+
+    #[test]
+    fn ignores_comments() {
+        let recording = Recording::parse("# terminal: xterm\n\nquery=\\e[c\n").unwrap();
+        assert_eq!(b"\x1b[c", recording.query.as_slice());
+    }
+
+    #[test]
+    fn rejects_malformed_fixtures() {
+        assert_eq!(None, Recording::parse("query"));
+        assert_eq!(None, Recording::parse("colors=256"));
+        assert_eq!(None, Recording::parse("reply=abc \\e[c"));
+        assert_eq!(None, Recording::parse("string_terminator=esc"));
+    }
+}
@@ -0,0 +1,213 @@
+use core::fmt;
+
+/// Whether it is a good idea to query the terminal right now.
+/// Retrieved by calling [`should_query`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum QueryDecision {
+    /// Nothing indicates that querying the terminal is a bad idea.
+    Query,
+    /// The terminal is likely shared with another program, querying it should be skipped.
+    Skip(SkipReason),
+}
+
+impl QueryDecision {
+    /// Returns `true` if the decision is [`QueryDecision::Query`].
+    pub fn should_query(&self) -> bool {
+        matches!(self, QueryDecision::Query)
+    }
+}
+
+/// The reason why [`should_query`] advises against querying the terminal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SkipReason {
+    /// Our process group is not the terminal's foreground process group,
+    /// e.g. because the program runs in the background (`your_program &`).
+    /// Reading from the terminal would stop the process with `SIGTTIN`.
+    NotForeground,
+    /// Standard output is connected to a pipe which is a strong indicator that
+    /// the output is read by another process, for instance a pager (`your_program | less`).
+    StdoutIsPipe,
+    /// An environment variable indicates that a pager is reading our output.
+    PagerInUse(&'static str),
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::NotForeground => {
+                f.write_str("the process is not in the terminal's foreground process group")
+            }
+            SkipReason::StdoutIsPipe => f.write_str("standard output is connected to a pipe"),
+            SkipReason::PagerInUse(var) => write!(f, "a pager is in use ({var} is set)"),
+        }
+    }
+}
+
+/// Environment variables set by programs that pipe their output into a pager.
+///
+/// Note that `PAGER` and `LESS` are not among them as they are
+/// commonly exported from shell profiles and say nothing about whether a pager is running.
+const PAGER_ENV_VARS: &[&str] = &["GIT_PAGER_IN_USE"];
+
+/// Heuristically determines if querying the terminal is safe.
+///
+/// Querying the terminal puts it into raw mode and reads the terminal's reply.
+/// If another program (such as a pager) uses the terminal at the same time,
+/// the two race for the terminal's mode and input. This function declines to query if:
+/// * our process group is not the terminal's foreground process group (Unix only),
+/// * standard output is connected to a pipe (Unix only),
+/// * an environment variable such as `GIT_PAGER_IN_USE` indicates that a pager is in use.
+///
+/// Like any heuristic, this has both false positives (e.g. `your_program | cat`)
+/// and false negatives (e.g. `your_program 2>&1 >/dev/tty | less`).
+/// Consider giving users an explicit option to override it
+/// (similar to `--color-theme=auto|dark|light`).
+///
+/// ```no_run
+/// use terminal_colorsaurus::{should_query, theme_mode, QueryDecision, QueryOptions};
+///
+/// match should_query() {
+///     QueryDecision::Skip(reason) => eprintln!("not detecting the theme mode: {reason}"),
+///     _ => println!("{:?}", theme_mode(QueryOptions::default())),
+/// }
+/// ```
+pub fn should_query() -> QueryDecision {
+    match skip_reason(&imp::Probe, |var| std::env::var_os(var).is_some()) {
+        Some(reason) => QueryDecision::Skip(reason),
+        None => QueryDecision::Query,
+    }
+}
+
+trait Probe {
+    /// Returns [`None`] if there's no terminal or the foreground process group is unknown.
+    fn is_foreground(&self) -> Option<bool>;
+    fn stdout_is_pipe(&self) -> bool;
+}
+
+fn skip_reason(probe: &impl Probe, is_set: impl Fn(&str) -> bool) -> Option<SkipReason> {
+    if probe.is_foreground() == Some(false) {
+        Some(SkipReason::NotForeground)
+    } else if probe.stdout_is_pipe() {
+        Some(SkipReason::StdoutIsPipe)
+    } else {
+        PAGER_ENV_VARS
+            .iter()
+            .find(|var| is_set(var))
+            .map(|var| SkipReason::PagerInUse(var))
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::fs::File;
+    use std::os::fd::{AsFd as _, AsRawFd as _};
+    use std::os::unix::fs::FileTypeExt as _;
+
+    pub(super) struct Probe;
+
+    impl super::Probe for Probe {
+        fn is_foreground(&self) -> Option<bool> {
+            let terminal = terminal_trx::terminal().ok()?;
+            // SAFETY: The file descriptor is valid as we're holding on to the terminal.
+            let foreground = unsafe { libc::tcgetpgrp(terminal.as_fd().as_raw_fd()) };
+            if foreground == -1 {
+                return None;
+            }
+            // SAFETY: getpgrp has no preconditions and always succeeds.
+            Some(foreground == unsafe { libc::getpgrp() })
+        }
+
+        fn stdout_is_pipe(&self) -> bool {
+            std::io::stdout()
+                .as_fd()
+                .try_clone_to_owned()
+                .and_then(|fd| File::from(fd).metadata())
+                .is_ok_and(|metadata| metadata.file_type().is_fifo())
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    pub(super) struct Probe;
+
+    impl super::Probe for Probe {
+        fn is_foreground(&self) -> Option<bool> {
+            None
+        }
+
+        fn stdout_is_pipe(&self) -> bool {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FakeProbe {
+        is_foreground: Option<bool>,
+        stdout_is_pipe: bool,
+    }
+
+    impl Probe for FakeProbe {
+        fn is_foreground(&self) -> Option<bool> {
+            self.is_foreground
+        }
+
+        fn stdout_is_pipe(&self) -> bool {
+            self.stdout_is_pipe
+        }
+    }
+
+    const INTERACTIVE: FakeProbe = FakeProbe {
+        is_foreground: Some(true),
+        stdout_is_pipe: false,
+    };
+
+    #[test]
+    fn queries_interactive_terminal() {
+        assert_eq!(None, skip_reason(&INTERACTIVE, |_| false));
+        let no_terminal = FakeProbe {
+            is_foreground: None,
+            ..INTERACTIVE
+        };
+        assert_eq!(None, skip_reason(&no_terminal, |_| false));
+    }
+
+    #[test]
+    fn skips_background_process() {
+        let background = FakeProbe {
+            is_foreground: Some(false),
+            stdout_is_pipe: true,
+        };
+        assert_eq!(
+            Some(SkipReason::NotForeground),
+            skip_reason(&background, |_| true)
+        );
+    }
+
+    #[test]
+    fn skips_piped_stdout() {
+        let piped = FakeProbe {
+            stdout_is_pipe: true,
+            ..INTERACTIVE
+        };
+        assert_eq!(
+            Some(SkipReason::StdoutIsPipe),
+            skip_reason(&piped, |_| false)
+        );
+    }
+
+    #[test]
+    fn skips_when_pager_is_in_use() {
+        assert_eq!(
+            Some(SkipReason::PagerInUse("GIT_PAGER_IN_USE")),
+            skip_reason(&INTERACTIVE, |var| var == "GIT_PAGER_IN_USE")
+        );
+        assert_eq!(None, skip_reason(&INTERACTIVE, |var| var == "PAGER"));
+    }
+}
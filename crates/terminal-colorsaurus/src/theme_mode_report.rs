@@ -0,0 +1,68 @@
+use crate::{ColorPalette, ThemeMode};
+use std::time::Duration;
+
+/// The terminal's theme mode together with how it was determined.
+/// Retrieved by calling [`theme_mode_report`](crate::theme_mode_report).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct ThemeModeReport {
+    /// The terminal's theme mode.
+    pub theme_mode: ThemeMode,
+    /// Where [`ThemeModeReport::theme_mode`] comes from.
+    pub source: ThemeModeSource,
+    /// The theme mode explicitly reported by the terminal in response to `CSI ? 996 n`.
+    pub reported: Option<ThemeMode>,
+    /// The terminal's foreground and background color, if the terminal supports querying them.
+    pub palette: Option<ColorPalette>,
+    /// How long it took for the terminal to reply to this query.
+    ///
+    /// The round trip is measured from the first attempt to read the reply
+    /// until the terminal's reply (including the reply to the `DA1` sentinel) has been read.
+    pub latency: Duration,
+}
+
+/// Describes how the [`ThemeMode`] of a [`ThemeModeReport`] was determined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ThemeModeSource {
+    /// The terminal explicitly reported its theme mode (`CSI ? 996 n`).
+    Terminal,
+    /// The theme mode was derived from the foreground and background color
+    /// using [`ColorPalette::theme_mode`].
+    ColorPalette,
+}
+
+impl ThemeModeReport {
+    /// Prefers the theme mode reported by the terminal over the one derived from the palette.
+    pub(crate) fn new(
+        reported: Option<ThemeMode>,
+        palette: Option<ColorPalette>,
+        latency: Duration,
+    ) -> Option<Self> {
+        let (theme_mode, source) = match (reported, &palette) {
+            (Some(reported), _) => (reported, ThemeModeSource::Terminal),
+            (None, Some(palette)) => (palette.theme_mode(), ThemeModeSource::ColorPalette),
+            (None, None) => return None,
+        };
+        Some(Self {
+            theme_mode,
+            source,
+            reported,
+            palette,
+            latency,
+        })
+    }
+
+    /// Returns `true` if the theme mode reported by the terminal
+    /// differs from the one derived from its colors.
+    ///
+    /// This can happen with themes where the background's lightness is close to the middle
+    /// or with terminals that report their color scheme preference independently of the
+    /// colors actually in use.
+    pub fn is_disagreement(&self) -> bool {
+        match (self.reported, &self.palette) {
+            (Some(reported), Some(palette)) => reported != palette.theme_mode(),
+            _ => false,
+        }
+    }
+}
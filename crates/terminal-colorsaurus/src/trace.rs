@@ -0,0 +1,40 @@
+// Instrumentation using `tracing`.
+// The macros expand to nothing when the `tracing` feature is disabled,
+// so that the instrumentation is free for everyone else.
+
+macro_rules! event {
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        tracing::$level!($($arg)+);
+    };
+}
+pub(crate) use event;
+
+// Records a value for a field declared as `Empty` on the current span.
+macro_rules! record {
+    ($field:literal, $value:expr) => {
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record($field, $value);
+    };
+}
+pub(crate) use record;
+
+#[cfg(feature = "tracing")]
+pub(crate) use caret::*;
+
+#[cfg(feature = "tracing")]
+mod caret {
+    use crate::fmt::CaretNotation;
+    use core::fmt;
+
+    // Shows bytes exchanged with the terminal in caret notation, so that
+    // escape sequences in log output don't mess with the terminal.
+    pub(crate) struct CaretBytes<'a>(pub(crate) &'a [u8]);
+
+    impl fmt::Display for CaretBytes<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            // FIXME(msrv): [1.79.0] Use `.utf8_chunks()` to avoid allocating.
+            fmt::Display::fmt(&CaretNotation(&String::from_utf8_lossy(self.0)), f)
+        }
+    }
+}
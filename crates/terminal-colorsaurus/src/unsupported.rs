@@ -1,13 +1,60 @@
-use crate::{Color, ColorPalette, Error, QueryOptions, Result};
+use crate::recording::Recording;
+use crate::{
+    Color, ColorPalette, Error, ExtendedColorPalette, QueryOptions, Result, TerminalGeometry,
+    ThemeModeReport, UnsupportedReason,
+};
 
 pub(crate) fn color_palette(_options: QueryOptions) -> Result<ColorPalette> {
-    Err(Error::unsupported())
+    Err(Error::unsupported(UnsupportedReason::Platform))
 }
 
 pub(crate) fn foreground_color(_options: QueryOptions) -> Result<Color> {
-    Err(Error::unsupported())
+    Err(Error::unsupported(UnsupportedReason::Platform))
 }
 
 pub(crate) fn background_color(_options: QueryOptions) -> Result<Color> {
-    Err(Error::unsupported())
+    Err(Error::unsupported(UnsupportedReason::Platform))
+}
+
+pub(crate) fn terminal_geometry(_options: QueryOptions) -> Result<TerminalGeometry> {
+    Err(Error::unsupported(UnsupportedReason::Platform))
+}
+
+pub(crate) fn extended_color_palette(_options: QueryOptions) -> Result<ExtendedColorPalette> {
+    Err(Error::unsupported(UnsupportedReason::Platform))
+}
+
+pub(crate) fn theme_mode_report(_options: QueryOptions) -> Result<ThemeModeReport> {
+    Err(Error::unsupported(UnsupportedReason::Platform))
+}
+
+pub(crate) fn reset_adaptive_timeout() {}
+
+pub(crate) fn record_color_palette(_options: QueryOptions) -> (Result<ColorPalette>, Recording) {
+    (
+        Err(Error::unsupported(UnsupportedReason::Platform)),
+        Recording::default(),
+    )
+}
+
+pub(crate) fn record_theme_mode_report(
+    _options: QueryOptions,
+) -> (Result<ThemeModeReport>, Recording) {
+    (
+        Err(Error::unsupported(UnsupportedReason::Platform)),
+        Recording::default(),
+    )
+}
+
+pub(crate) fn record_extended_color_palette(
+    _options: QueryOptions,
+) -> (Result<ExtendedColorPalette>, Recording) {
+    (
+        Err(Error::unsupported(UnsupportedReason::Platform)),
+        Recording::default(),
+    )
+}
+
+pub(crate) fn replay_color_palette(_recording: &Recording) -> Result<ColorPalette> {
+    Err(Error::unsupported(UnsupportedReason::Platform))
 }
@@ -1,67 +1,378 @@
-use crate::io::{read_until2, TermReader};
-use crate::quirks::{terminal_quirks_from_env, TerminalQuirks};
-use crate::{Color, ColorPalette, Error, QueryOptions, Result};
-use std::io::{self, BufRead, BufReader, Write as _};
+use crate::io::{
+    read_until2, read_until_match, Connect, Record, Replay, TermReader, Transport, Tty,
+};
+use crate::quirks::{terminal_quirks, TerminalQuirks};
+use crate::recording::Recording;
+use crate::trace;
+#[cfg(feature = "tracing")]
+use crate::trace::CaretBytes;
+use crate::{
+    Color, ColorPalette, Error, ExtendedColorPalette, Phase, QueryKind, QueryOptions, Result, Size,
+    TerminalGeometry, ThemeMode, ThemeModeReport, UnsupportedReason, ANSI_PALETTE_LEN,
+};
+use std::env;
+use std::io::{self, BufRead, BufReader};
+use std::sync::Mutex;
 use std::time::Duration;
-use terminal_trx::{terminal, RawModeGuard};
 
 const QUERY_FG: &[u8] = b"\x1b]10;?";
 const FG_RESPONSE_PREFIX: &[u8] = b"\x1b]10;";
 const QUERY_BG: &[u8] = b"\x1b]11;?";
 const BG_RESPONSE_PREFIX: &[u8] = b"\x1b]11;";
+const CURSOR_RESPONSE_PREFIX: &[u8] = b"\x1b]12;";
+const SELECTION_BG_RESPONSE_PREFIX: &[u8] = b"\x1b]17;";
+const SELECTION_FG_RESPONSE_PREFIX: &[u8] = b"\x1b]19;";
+const QUERY_COLOR_SCHEME: &[u8] = b"\x1b[?996n";
+const COLOR_SCHEME_RESPONSE_PREFIX: &[u8] = b"\x1b[?997;";
+const QUERY_TEXT_AREA_PIXELS: &[u8] = b"\x1b[14t";
+const QUERY_CELL_PIXELS: &[u8] = b"\x1b[16t";
+const QUERY_TEXT_AREA_CELLS: &[u8] = b"\x1b[18t";
 
 pub(crate) fn foreground_color(options: QueryOptions) -> Result<Color> {
-    let quirks = terminal_quirks_from_env();
-    let response = query(
+    let quirks = quirks_for_options(&options);
+    let (response, _) = query(
         &options,
         quirks,
+        Tty,
         |w| write_query(w, quirks, QUERY_FG),
         read_color_response,
     )
-    .map_err(map_timed_out_err(options.timeout))?;
-    parse_response(response, FG_RESPONSE_PREFIX)
+    .map_err(|e| e.for_query(QueryKind::Foreground))?;
+    parse_response(response, FG_RESPONSE_PREFIX).map_err(|e| e.for_query(QueryKind::Foreground))
 }
 
 pub(crate) fn background_color(options: QueryOptions) -> Result<Color> {
-    let quirks = terminal_quirks_from_env();
-    let response = query(
+    let quirks = quirks_for_options(&options);
+    let (response, _) = query(
         &options,
         quirks,
+        Tty,
         |w| write_query(w, quirks, QUERY_BG),
         read_color_response,
     )
-    .map_err(map_timed_out_err(options.timeout))?;
-    parse_response(response, BG_RESPONSE_PREFIX)
+    .map_err(|e| e.for_query(QueryKind::Background))?;
+    parse_response(response, BG_RESPONSE_PREFIX).map_err(|e| e.for_query(QueryKind::Background))
 }
 
 pub(crate) fn color_palette(options: QueryOptions) -> Result<ColorPalette> {
-    let quirks = terminal_quirks_from_env();
-    let (fg_response, bg_response) = query(
-        &options,
+    let quirks = quirks_for_options(&options);
+    color_palette_with(&options, quirks, Tty).map(|(palette, _)| palette)
+}
+
+pub(crate) fn record_color_palette(options: QueryOptions) -> (Result<ColorPalette>, Recording) {
+    record(options, |options, quirks, connect| {
+        color_palette_with(options, quirks, connect).map(|(palette, _)| palette)
+    })
+}
+
+pub(crate) fn record_theme_mode_report(
+    options: QueryOptions,
+) -> (Result<ThemeModeReport>, Recording) {
+    record(options, |options, quirks, connect| {
+        theme_mode_report_with(options, quirks, connect)
+    })
+}
+
+pub(crate) fn record_extended_color_palette(
+    options: QueryOptions,
+) -> (Result<ExtendedColorPalette>, Recording) {
+    record(options, |options, quirks, connect| {
+        extended_color_palette_with(options, quirks, connect)
+    })
+}
+
+// The recorded timeout is fixed, so that replaying the recording behaves the same.
+fn record<T>(
+    options: QueryOptions,
+    query: impl FnOnce(&QueryOptions, TerminalQuirks, Record<'_, Tty>) -> Result<T>,
+) -> (Result<T>, Recording) {
+    let mut recording = Recording {
+        quirks: quirks_for_options(&options),
+        timeout: effective_timeout(&options),
+        ..Default::default()
+    };
+    let quirks = recording.quirks;
+    let options = QueryOptions {
+        timeout: recording.timeout,
+        adaptive_timeout: false,
+        ..options
+    };
+    let result = query(&options, quirks, Record::new(Tty, &mut recording));
+    (result, recording)
+}
+
+pub(crate) fn replay_color_palette(recording: &Recording) -> Result<ColorPalette> {
+    let options = QueryOptions {
+        timeout: recording.timeout,
+        ..Default::default()
+    };
+    color_palette_with(&options, recording.quirks, Replay { recording }).map(|(palette, _)| palette)
+}
+
+fn color_palette_with(
+    options: &QueryOptions,
+    quirks: TerminalQuirks,
+    connect: impl Connect,
+) -> Result<(ColorPalette, Duration)> {
+    let ((fg_response, bg_response), latency) = query(
+        options,
         quirks,
+        connect,
         |w| write_query(w, quirks, QUERY_FG).and_then(|_| write_query(w, quirks, QUERY_BG)),
-        |r| Ok((read_color_response(r)?, read_color_response(r)?)),
-    )
-    .map_err(map_timed_out_err(options.timeout))?;
-    let foreground = parse_response(fg_response, FG_RESPONSE_PREFIX)?;
-    let background = parse_response(bg_response, BG_RESPONSE_PREFIX)?;
-    Ok(ColorPalette {
+        |r| {
+            let fg_response =
+                read_color_response(r).map_err(|e| e.for_query(QueryKind::Foreground))?;
+            let bg_response =
+                read_color_response(r).map_err(|e| e.for_query(QueryKind::Background))?;
+            Ok((fg_response, bg_response))
+        },
+    )?;
+    let foreground = parse_response(fg_response, FG_RESPONSE_PREFIX)
+        .map_err(|e| e.for_query(QueryKind::Foreground))?;
+    let background = parse_response(bg_response, BG_RESPONSE_PREFIX)
+        .map_err(|e| e.for_query(QueryKind::Background))?;
+    let palette = ColorPalette {
         foreground,
         background,
-    })
+    };
+    Ok((palette, latency))
+}
+
+pub(crate) fn terminal_geometry(options: QueryOptions) -> Result<TerminalGeometry> {
+    let quirks = quirks_for_options(&options);
+    let (responses, _) = query_until_da1(
+        &options,
+        quirks,
+        Tty,
+        |w| {
+            quirks.write_all(w, QUERY_TEXT_AREA_PIXELS)?;
+            quirks.write_all(w, QUERY_CELL_PIXELS)?;
+            quirks.write_all(w, QUERY_TEXT_AREA_CELLS)
+        },
+        |r| read_responses_until_da1(r, quirks),
+    )
+    .map_err(|e| e.for_query(QueryKind::Geometry))?;
+    Ok(parse_geometry_responses(&responses))
+}
+
+pub(crate) fn theme_mode_report(options: QueryOptions) -> Result<ThemeModeReport> {
+    let quirks = quirks_for_options(&options);
+    theme_mode_report_with(&options, quirks, Tty)
+}
+
+fn theme_mode_report_with(
+    options: &QueryOptions,
+    quirks: TerminalQuirks,
+    connect: impl Connect,
+) -> Result<ThemeModeReport> {
+    if !options.query_color_scheme {
+        let (palette, latency) = color_palette_with(options, quirks, connect)?;
+        return Ok(ThemeModeReport::new(None, Some(palette), latency)
+            .expect("a palette is enough for a report"));
+    }
+    let (responses, latency) = query_until_da1(
+        options,
+        quirks,
+        connect,
+        |w| {
+            write_query(w, quirks, QUERY_FG)?;
+            write_query(w, quirks, QUERY_BG)?;
+            quirks.write_all(w, QUERY_COLOR_SCHEME)
+        },
+        |r| read_responses_until_da1(r, quirks),
+    )
+    .map_err(|e| for_pending_query(e, &theme_mode_replies()))?;
+    parse_theme_mode_responses(&responses, quirks, latency)
+}
+
+fn theme_mode_replies() -> Vec<(Vec<u8>, QueryKind)> {
+    vec![
+        (FG_RESPONSE_PREFIX.to_vec(), QueryKind::Foreground),
+        (BG_RESPONSE_PREFIX.to_vec(), QueryKind::Background),
+        (
+            COLOR_SCHEME_RESPONSE_PREFIX.to_vec(),
+            QueryKind::ColorScheme,
+        ),
+    ]
+}
+
+fn parse_theme_mode_responses(
+    responses: &[Vec<u8>],
+    quirks: TerminalQuirks,
+    latency: Duration,
+) -> Result<ThemeModeReport> {
+    let reported = responses
+        .iter()
+        .find_map(|r| parse_color_scheme_response(r));
+    let palette = parse_osc_responses(responses).map(|p| p.color_palette());
+    if let Some(report) = ThemeModeReport::new(reported, palette, latency) {
+        return Ok(report);
+    }
+    // We only get here once the reply to DA1 arrived, so if the terminal didn't answer
+    // a single one of our queries before it, the terminal ignored them.
+    let answered = responses
+        .iter()
+        .any(|r| osc_response_body(r).is_some() || r.starts_with(COLOR_SCHEME_RESPONSE_PREFIX));
+    if quirks.answers_da1 && !answered {
+        Err(Error::unsupported(UnsupportedReason::Da1Sentinel)
+            .in_phase(Phase::Read)
+            .for_query(QueryKind::Foreground))
+    } else {
+        Err(missing_colors(responses))
+    }
+}
+
+pub(crate) fn extended_color_palette(options: QueryOptions) -> Result<ExtendedColorPalette> {
+    let quirks = quirks_for_options(&options);
+    extended_color_palette_with(&options, quirks, Tty)
+}
+
+fn extended_color_palette_with(
+    options: &QueryOptions,
+    quirks: TerminalQuirks,
+    mut connect: impl Connect,
+) -> Result<ExtendedColorPalette> {
+    let (kitty_responses, _) = query_until_da1(
+        options,
+        quirks,
+        &mut connect,
+        |w| write_query(w, quirks, &kitty_query()),
+        |r| read_responses_until_da1(r, quirks),
+    )
+    .map_err(|e| e.for_query(QueryKind::KittyColors))?;
+    if let Some(palette) = parse_kitty_responses(&kitty_responses) {
+        return Ok(palette);
+    }
+
+    // The terminal doesn't support OSC 21, so we fall back to querying each color individually.
+    osc_color_palette_with(options, quirks, connect)
+}
+
+fn osc_color_palette_with(
+    options: &QueryOptions,
+    quirks: TerminalQuirks,
+    connect: impl Connect,
+) -> Result<ExtendedColorPalette> {
+    let replies = osc_palette_replies();
+    let (responses, _) = query_until_da1(
+        options,
+        quirks,
+        connect,
+        |w| {
+            for (prefix, _) in &replies {
+                write_query(w, quirks, &[prefix, &b"?"[..]].concat())?;
+            }
+            Ok(())
+        },
+        |r| read_responses_until_da1(r, quirks),
+    )
+    .map_err(|e| for_pending_query(e, &replies))?;
+    parse_osc_responses(&responses).ok_or_else(|| missing_colors(&responses))
+}
+
+// The queries for the full palette, identified by the prefix that both the query and its reply start with.
+fn osc_palette_replies() -> Vec<(Vec<u8>, QueryKind)> {
+    let mut replies = vec![
+        (FG_RESPONSE_PREFIX.to_vec(), QueryKind::Foreground),
+        (BG_RESPONSE_PREFIX.to_vec(), QueryKind::Background),
+        (CURSOR_RESPONSE_PREFIX.to_vec(), QueryKind::Cursor),
+        (SELECTION_BG_RESPONSE_PREFIX.to_vec(), QueryKind::Selection),
+        (SELECTION_FG_RESPONSE_PREFIX.to_vec(), QueryKind::Selection),
+    ];
+    replies.extend((0..=u8::MAX).map(|index| {
+        (
+            format!("\x1b]4;{index};").into_bytes(),
+            QueryKind::Palette(index),
+        )
+    }));
+    replies
+}
+
+// Terminals answer queries in order and skip the ones they don't support,
+// so the query that we were waiting for is the one after the last reply we received.
+fn for_pending_query(error: Error, replies: &[(Vec<u8>, QueryKind)]) -> Error {
+    let mut received = error.context().received.as_slice();
+    let mut pending = 0;
+    while let Ok(Some(response)) = read_response(&mut received) {
+        // The last reply might have been cut off.
+        let complete = osc_response_body(&response).is_some()
+            || (response.starts_with(b"\x1b[")
+                && response.last().copied().is_some_and(is_csi_final_byte));
+        if !complete {
+            break;
+        }
+        if let Some(index) = replies
+            .iter()
+            .position(|(prefix, _)| response.starts_with(prefix))
+        {
+            pending = index + 1;
+        }
+    }
+    match replies.get(pending) {
+        Some((_, query)) => error.for_query(*query),
+        None => error,
+    }
+}
+
+// Without the foreground and background color we can't tell the theme mode.
+fn missing_colors(responses: &[Vec<u8>]) -> Error {
+    let has_foreground = responses.iter().any(|r| r.starts_with(FG_RESPONSE_PREFIX));
+    let query = if has_foreground {
+        QueryKind::Background
+    } else {
+        QueryKind::Foreground
+    };
+    Error::unsupported(UnsupportedReason::MissingColors)
+        .in_phase(Phase::Parse)
+        .for_query(query)
+}
+
+fn quirks_for_options(options: &QueryOptions) -> TerminalQuirks {
+    let mut quirks = terminal_quirks();
+    quirks.string_terminator = options.string_terminator.resolve(quirks);
+    quirks
 }
 
 fn write_query(w: &mut dyn io::Write, quirks: TerminalQuirks, query: &[u8]) -> io::Result<()> {
-    quirks.write_all(w, query)?;
-    quirks.write_string_terminator(w)?;
-    Ok(())
+    // The query is written in one go so that it can be wrapped for passthrough as a whole.
+    let query = [query, quirks.string_terminator()].concat();
+    trace::event!(trace, query = %CaretBytes(&query), "writing query");
+    quirks.write_all(w, &query)
+}
+
+const ADAPTIVE_TIMEOUT_MIN: Duration = Duration::from_millis(200);
+const ADAPTIVE_TIMEOUT_LATENCY_FACTOR: u32 = 4;
+
+// The latency of the most recent query, which the adaptive timeout of later queries is based on.
+// It is shared by all threads, as they talk to the same terminal (see `reset_adaptive_timeout`).
+static LAST_LATENCY: Mutex<Option<Duration>> = Mutex::new(None);
+
+pub(crate) fn reset_adaptive_timeout() {
+    *LAST_LATENCY.lock().unwrap_or_else(|e| e.into_inner()) = None;
 }
 
-fn map_timed_out_err(timeout: Duration) -> impl Fn(Error) -> Error {
-    move |e| match e {
-        Error::Io(io) if io.kind() == io::ErrorKind::TimedOut => Error::Timeout(timeout),
-        e => e,
+fn last_latency() -> Option<Duration> {
+    *LAST_LATENCY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn record_latency(latency: Duration) {
+    *LAST_LATENCY.lock().unwrap_or_else(|e| e.into_inner()) = Some(latency);
+}
+
+// Local terminals usually answer in well under a millisecond (see the latency measurements),
+// so we start with a short timeout and only extend it if there's evidence of high latency.
+fn effective_timeout(options: &QueryOptions) -> Duration {
+    if !options.adaptive_timeout {
+        return options.timeout;
     }
+    let timeout = if let Some(latency) = last_latency() {
+        ADAPTIVE_TIMEOUT_MIN.max(latency.saturating_mul(ADAPTIVE_TIMEOUT_LATENCY_FACTOR))
+    } else if env::var_os("SSH_TTY").is_some() || env::var_os("SSH_CONNECTION").is_some() {
+        options.timeout
+    } else {
+        ADAPTIVE_TIMEOUT_MIN
+    };
+    timeout.min(options.timeout)
 }
 
 const ST: &[u8] = b"\x1b\\";
@@ -70,11 +381,202 @@ const ESC: u8 = 0x1b;
 const BEL: u8 = 0x07;
 
 fn parse_response(response: Vec<u8>, prefix: &[u8]) -> Result<Color> {
-    response
+    let Some(color) = response
         .strip_prefix(prefix)
         .and_then(|r| r.strip_suffix(ST).or(r.strip_suffix(&[BEL])))
-        .and_then(xparsecolor)
-        .ok_or(Error::Parse(response))
+    else {
+        trace::event!(debug, response = %CaretBytes(&response), "invalid response");
+        return Err(Error::invalid_response(response).in_phase(Phase::Parse));
+    };
+    let color = xparsecolor(color);
+    trace::event!(debug, response = %CaretBytes(&response), ?color, "parsed response");
+    color.ok_or_else(|| Error::parse(response).in_phase(Phase::Parse))
+}
+
+// Replies have the form `CSI ? 997 ; <mode> n` where `<mode>` is 1 for dark and 2 for light.
+// See: https://contour-terminal.org/vt-extensions/color-palette-update-notifications/
+fn parse_color_scheme_response(response: &[u8]) -> Option<ThemeMode> {
+    let mode = response
+        .strip_prefix(COLOR_SCHEME_RESPONSE_PREFIX)?
+        .strip_suffix(b"n")?;
+    match mode {
+        b"1" => Some(ThemeMode::Dark),
+        b"2" => Some(ThemeMode::Light),
+        _ => None,
+    }
+}
+
+// Replies have the form `CSI <kind> ; <height> ; <width> t`
+// where `<kind>` is the query's parameter minus 10.
+fn parse_geometry_responses(responses: &[Vec<u8>]) -> TerminalGeometry {
+    let mut geometry = TerminalGeometry::default();
+    for response in responses {
+        let Some((kind, size)) = parse_geometry_response(response) else {
+            continue;
+        };
+        match kind {
+            b"4" => geometry.text_area_pixels = Some(size),
+            b"6" => geometry.cell_pixels = Some(size),
+            b"8" => geometry.text_area_cells = Some(size),
+            _ => {}
+        }
+    }
+    geometry
+}
+
+fn parse_geometry_response(response: &[u8]) -> Option<(&[u8], Size)> {
+    let params = response.strip_prefix(b"\x1b[")?.strip_suffix(b"t")?;
+    let mut params = params.split(|b| *b == b';');
+    let kind = params.next()?;
+    let height = parse_u16(params.next()?)?;
+    let width = parse_u16(params.next()?)?;
+    if params.next().is_some() || width == 0 || height == 0 {
+        return None;
+    }
+    Some((kind, Size { width, height }))
+}
+
+fn parse_u16(input: &[u8]) -> Option<u16> {
+    std::str::from_utf8(input).ok()?.parse().ok()
+}
+
+const KITTY_RESPONSE_PREFIX: &[u8] = b"21;";
+const KITTY_TRANSPARENT_BG_KEY_PREFIX: &str = "transparent_background_color";
+const KITTY_TRANSPARENT_BG_COUNT: usize = 7;
+
+// See: https://sw.kovidgoyal.net/kitty/color-stack/#setting-and-querying-colors
+fn kitty_query() -> Vec<u8> {
+    let mut query = b"\x1b]21".to_vec();
+    let keys = [
+        "foreground",
+        "background",
+        "cursor",
+        "cursor_text",
+        "selection_foreground",
+        "selection_background",
+    ];
+    for key in keys {
+        query.extend_from_slice(format!(";{key}=?").as_bytes());
+    }
+    for index in 1..=KITTY_TRANSPARENT_BG_COUNT {
+        query.extend_from_slice(format!(";{KITTY_TRANSPARENT_BG_KEY_PREFIX}{index}=?").as_bytes());
+    }
+    for index in 0..ANSI_PALETTE_LEN {
+        query.extend_from_slice(format!(";{index}=?").as_bytes());
+    }
+    query
+}
+
+// Replies have the form `OSC 21 ; <key>=<color> ; ... ST` where
+// `<color>` is empty if the color is not set.
+fn parse_kitty_responses(responses: &[Vec<u8>]) -> Option<ExtendedColorPalette> {
+    let body = responses
+        .iter()
+        .filter_map(|r| osc_response_body(r))
+        .find_map(|body| body.strip_prefix(KITTY_RESPONSE_PREFIX))?;
+
+    let mut foreground = None;
+    let mut background = None;
+    let mut palette = empty_extended_palette();
+    let mut transparent_backgrounds = vec![None; KITTY_TRANSPARENT_BG_COUNT];
+
+    for pair in body.split(|b| *b == b';') {
+        let Some(separator) = pair.iter().position(|b| *b == b'=') else {
+            continue;
+        };
+        let (Ok(key), value) = (
+            std::str::from_utf8(&pair[..separator]),
+            &pair[separator + 1..],
+        ) else {
+            continue;
+        };
+        let color = xparsecolor(value);
+        match key {
+            "foreground" => foreground = color,
+            "background" => background = color,
+            "cursor" => palette.cursor = color,
+            "cursor_text" => palette.cursor_text = color,
+            "selection_foreground" => palette.selection_foreground = color,
+            "selection_background" => palette.selection_background = color,
+            _ => {
+                if let Some(index) = key.strip_prefix(KITTY_TRANSPARENT_BG_KEY_PREFIX) {
+                    if let Some(slot) = parse_index(index)
+                        .and_then(|i| i.checked_sub(1))
+                        .and_then(|i| transparent_backgrounds.get_mut(i))
+                    {
+                        *slot = color;
+                    }
+                } else if let Some(slot) = parse_index(key).and_then(|i| palette.ansi.get_mut(i)) {
+                    *slot = color;
+                }
+            }
+        }
+    }
+
+    palette.foreground = foreground?;
+    palette.background = background?;
+    palette.transparent_backgrounds = transparent_backgrounds.into_iter().flatten().collect();
+    Some(palette)
+}
+
+// Replies have the form `OSC <number> ; <color> ST` except for
+// the indexed colors which are reported as `OSC 4 ; <index> ; <color> ST`.
+fn parse_osc_responses(responses: &[Vec<u8>]) -> Option<ExtendedColorPalette> {
+    let mut foreground = None;
+    let mut background = None;
+    let mut palette = empty_extended_palette();
+
+    for body in responses.iter().filter_map(|r| osc_response_body(r)) {
+        let mut parts = body.splitn(2, |b| *b == b';');
+        let (Some(number), Some(rest)) = (parts.next(), parts.next()) else {
+            continue;
+        };
+        match number {
+            b"10" => foreground = xparsecolor(rest),
+            b"11" => background = xparsecolor(rest),
+            b"12" => palette.cursor = xparsecolor(rest),
+            b"17" => palette.selection_background = xparsecolor(rest),
+            b"19" => palette.selection_foreground = xparsecolor(rest),
+            b"4" => {
+                let mut parts = rest.splitn(2, |b| *b == b';');
+                let (Some(index), Some(color)) = (parts.next(), parts.next()) else {
+                    continue;
+                };
+                let index = std::str::from_utf8(index).ok().and_then(parse_index);
+                if let Some(slot) = index.and_then(|i| palette.ansi.get_mut(i)) {
+                    *slot = xparsecolor(color);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    palette.foreground = foreground?;
+    palette.background = background?;
+    Some(palette)
+}
+
+fn empty_extended_palette() -> ExtendedColorPalette {
+    ExtendedColorPalette {
+        foreground: Color::default(),
+        background: Color::default(),
+        cursor: None,
+        cursor_text: None,
+        selection_foreground: None,
+        selection_background: None,
+        transparent_backgrounds: Vec::new(),
+        ansi: vec![None; ANSI_PALETTE_LEN],
+    }
+}
+
+fn osc_response_body(response: &[u8]) -> Option<&[u8]> {
+    response
+        .strip_prefix(b"\x1b]")
+        .and_then(|r| r.strip_suffix(ST).or(r.strip_suffix(&[BEL])))
+}
+
+fn parse_index(input: &str) -> Option<usize> {
+    input.parse().ok()
 }
 
 fn xparsecolor(input: &[u8]) -> Option<Color> {
@@ -87,7 +589,7 @@ fn xparsecolor(input: &[u8]) -> Option<Color> {
     Some(Color { r, g, b })
 }
 
-type Reader<'a> = BufReader<TermReader<RawModeGuard<'a>>>;
+type Reader<'a> = BufReader<TermReader<&'a mut dyn Transport>>;
 
 // We detect terminals that don't support the color query in quite a smart way:
 // First, we send the color query and then a query that we know is well-supported (DA1).
@@ -95,61 +597,643 @@ type Reader<'a> = BufReader<TermReader<RawModeGuard<'a>>>;
 // it does not support querying for colors.
 //
 // Source: https://gitlab.freedesktop.org/terminal-wg/specifications/-/issues/8#note_151381
+//
+// Returns the response together with the time it took for the terminal to reply.
 fn query<T>(
     options: &QueryOptions,
     quirks: TerminalQuirks,
+    connect: impl Connect,
     write_query: impl FnOnce(&mut dyn io::Write) -> io::Result<()>,
     read_response: impl FnOnce(&mut Reader<'_>) -> Result<T>,
-) -> Result<T> {
-    if quirks.is_known_unsupported() {
-        return Err(Error::unsupported());
-    }
+) -> Result<(T, Duration)> {
+    query_until_da1(options, quirks, connect, write_query, |r| {
+        let response = read_response(r)?;
 
-    let mut tty = terminal()?;
-    let mut tty = tty.lock();
-    let mut tty = tty.enable_raw_mode()?;
+        // We still need to consume the response to DA1
+        // Let's ignore errors, they are not that important.
+        if quirks.answers_da1 {
+            _ = consume_da1_response(r);
+        }
 
-    write_query(&mut tty)?;
-    quirks.write_all(&mut tty, DA1)?;
-    tty.flush()?;
+        Ok(response)
+    })
+}
 
-    let mut reader = BufReader::with_capacity(32, TermReader::new(tty, options.timeout));
+// Same as `query` except that `read_response` is responsible
+// for consuming the response to DA1.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        name = "query",
+        level = "debug",
+        skip_all,
+        fields(?quirks, timeout = tracing::field::Empty, elapsed = tracing::field::Empty)
+    )
+)]
+fn query_until_da1<T>(
+    options: &QueryOptions,
+    quirks: TerminalQuirks,
+    mut connect: impl Connect,
+    write_query: impl FnOnce(&mut dyn io::Write) -> io::Result<()>,
+    read_response: impl FnOnce(&mut Reader<'_>) -> Result<T>,
+) -> Result<(T, Duration)> {
+    if quirks.is_known_unsupported() {
+        trace::event!(
+            debug,
+            "not querying the terminal, it's known to be unsupported"
+        );
+        return Err(Error::unsupported(UnsupportedReason::Quirks));
+    }
 
-    let response = read_response(&mut reader)?;
+    connect.connect(|mut tty| {
+        write_query(&mut tty)
+            .and_then(|_| {
+                if quirks.answers_da1 {
+                    quirks.write_all(&mut tty, DA1)?;
+                }
+                tty.flush()
+            })
+            .map_err(|e| Error::from(e).in_phase(Phase::Write))?;
 
-    // We still need to consume the response to DA1
-    // Let's ignore errors, they are not that important.
-    _ = consume_da1_response(&mut reader, true);
+        let timeout = effective_timeout(options);
+        trace::record!("timeout", tracing::field::debug(timeout));
+        let mut reader =
+            BufReader::with_capacity(32, TermReader::new(tty, timeout, MAX_RESPONSE_LEN));
+        // A terminal that answers DA1 replies eventually, so if it hasn't replied
+        // within the short timeout, it's slow rather than unsupported.
+        if options.adaptive_timeout && quirks.answers_da1 {
+            reader.get_mut().extend_timeout_to(options.timeout);
+        }
 
-    Ok(response)
+        let result = read_response(&mut reader).map_err(|e| {
+            e.in_phase(Phase::Read)
+                .with_received(reader.get_ref().received())
+        });
+        trace::record!("elapsed", tracing::field::debug(reader.get_ref().elapsed()));
+        match result {
+            Ok(response) => {
+                let latency = reader.get_ref().elapsed();
+                record_latency(latency);
+                Ok((response, latency))
+            }
+            Err(Error::Io(e)) if e.error.kind() == io::ErrorKind::TimedOut => {
+                let timeout = reader.get_ref().timeout();
+                // Remember that the terminal is slow, so that the next query waits longer.
+                record_latency(timeout);
+                // The replies might still arrive after we've given up on them. We keep raw mode enabled
+                // and consume them until the response to DA1 arrives so that they don't end up on the screen.
+                let mut drain_timeout = timeout.saturating_add(options.grace_period);
+                if options.adaptive_timeout {
+                    drain_timeout = drain_timeout.max(options.timeout);
+                }
+                if drain_timeout > timeout {
+                    reader.get_mut().set_timeout(drain_timeout);
+                    _ = consume_responses_until_da1(&mut reader);
+                }
+                trace::event!(debug, received = %CaretBytes(&e.context.received), "timed out");
+                Err(Error::timeout(timeout, e.context))
+            }
+            Err(e) => {
+                trace::event!(debug, error = %e, "failed to read the reply");
+                Err(e)
+            }
+        }
+    })
 }
 
 fn read_color_response(r: &mut Reader<'_>) -> Result<Vec<u8>> {
     let mut buf = Vec::new();
-    r.read_until(ESC, &mut buf)?; // Both responses start with ESC
+    read_introducer(r, &mut buf)?; // Both responses start with ESC
+
+    match buf.last() {
+        Some(b']') => {}
+        // If we get the response for DA1 back first, then we know that
+        // the terminal does not recocgnize the color query.
+        Some(b'[') => {
+            _ = read_until_match(r, |b| b == b'c', &mut buf, MAX_RESPONSE_LEN);
+            return Err(Error::unsupported(UnsupportedReason::Da1Sentinel));
+        }
+        _ => return Err(Error::invalid_response(buf)),
+    }
+
+    read_osc_response_rest(r, &mut buf)?;
+    Ok(buf)
+}
 
-    // If we get the response for DA1 back first, then we know that
-    // the terminal does not recocgnize the color query.
-    if !r.buffer().starts_with(b"]") {
-        _ = consume_da1_response(r, false);
-        return Err(Error::unsupported());
+// A terminal that never terminates its reply (be it misbehaving or malicious)
+// shouldn't make us buffer an unbounded amount of data until the timeout elapses.
+// The longest legitimate reply is kitty's reply to `OSC 21` with about 7 KiB.
+const MAX_RESPONSE_LEN: usize = 16 * 1024;
+const MAX_RESPONSES_LEN: usize = 4 * MAX_RESPONSE_LEN;
+
+// Reads the `ESC` that starts a reply and the byte after it.
+// Anything other than `ESC` before that is not a reply.
+fn read_introducer(r: &mut impl BufRead, buf: &mut Vec<u8>) -> Result<()> {
+    let mut byte = [0];
+    r.read_exact(&mut byte)?;
+    buf.push(byte[0]);
+    if byte[0] != ESC {
+        return Err(Error::invalid_response(std::mem::take(buf)));
     }
+    r.read_exact(&mut byte)?;
+    buf.push(byte[0]);
+    Ok(())
+}
 
+fn read_osc_response_rest(r: &mut impl BufRead, buf: &mut Vec<u8>) -> Result<()> {
     // Some terminals always respond with BEL (see terminal survey).
-    read_until2(r, BEL, ESC, &mut buf)?;
+    read_until2(r, BEL, ESC, buf, MAX_RESPONSE_LEN)?;
     if buf.last() == Some(&ESC) {
-        r.read_until(b'\\', &mut buf)?;
+        read_until_match(r, |b| b == b'\\', buf, MAX_RESPONSE_LEN)?;
     }
+    ensure_terminated(buf, |b| b == BEL || b == b'\\')
+}
 
-    Ok(buf)
+// The bounded reads stop at the length limit without finding the terminator.
+fn ensure_terminated(buf: &mut Vec<u8>, is_terminator: impl Fn(u8) -> bool) -> Result<()> {
+    if buf.len() >= MAX_RESPONSE_LEN && !buf.last().is_some_and(|b| is_terminator(*b)) {
+        return Err(Error::invalid_response(std::mem::take(buf)));
+    }
+    Ok(())
+}
+
+// Collects the replies to `OSC` and `CSI` queries. Since the terminal answers queries in order
+// and silently ignores queries it doesn't understand, we know that there are no more
+// replies to come once we see the response to DA1 (`CSI ? ... c`).
+//
+// For terminals that don't answer DA1, we collect replies until the timeout elapses instead.
+fn read_responses_until_da1(r: &mut impl BufRead, quirks: TerminalQuirks) -> Result<Vec<Vec<u8>>> {
+    let mut responses = Vec::new();
+    let mut len = 0;
+    loop {
+        match read_response(r) {
+            Ok(Some(response)) => {
+                len += response.len();
+                if len > MAX_RESPONSES_LEN {
+                    return Err(Error::invalid_response(response));
+                }
+                responses.push(response);
+            }
+            Ok(None) => return Ok(responses),
+            Err(Error::Io(e))
+                if e.error.kind() == io::ErrorKind::TimedOut && !quirks.answers_da1 =>
+            {
+                return Ok(responses)
+            }
+            Err(e) => return Err(e),
+        }
+    }
 }
 
-fn consume_da1_response(r: &mut impl BufRead, consume_esc: bool) -> io::Result<()> {
+fn consume_responses_until_da1(r: &mut impl BufRead) -> Result<()> {
+    loop {
+        match read_response(r) {
+            Ok(Some(_)) => {}
+            Ok(None) => return Ok(()),
+            // We might start reading in the middle of a reply, so we skip to the next one.
+            Err(Error::InvalidResponse(_)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// Reads a single `OSC` or `CSI` reply. Returns `None` for the response to DA1.
+fn read_response(r: &mut impl BufRead) -> Result<Option<Vec<u8>>> {
     let mut buf = Vec::new();
-    if consume_esc {
-        r.read_until(ESC, &mut buf)?;
+    read_introducer(r, &mut buf)?;
+    match buf.last() {
+        Some(b'[') => {
+            read_until_match(r, is_csi_final_byte, &mut buf, MAX_RESPONSE_LEN)?;
+            ensure_terminated(&mut buf, is_csi_final_byte)?;
+            if buf.starts_with(b"\x1b[?") && buf.ends_with(b"c") {
+                return Ok(None);
+            }
+        }
+        Some(b']') => read_osc_response_rest(r, &mut buf)?,
+        _ => return Err(Error::invalid_response(buf)),
     }
-    r.read_until(b'[', &mut buf)?;
-    r.read_until(b'c', &mut buf)?;
+    Ok(Some(buf))
+}
+
+fn is_csi_final_byte(byte: u8) -> bool {
+    (0x40..=0x7e).contains(&byte)
+}
+
+fn consume_da1_response(r: &mut impl BufRead) -> io::Result<()> {
+    let mut buf = Vec::new();
+    read_until_match(r, |b| b == ESC, &mut buf, MAX_RESPONSE_LEN)?;
+    read_until_match(r, |b| b == b'[', &mut buf, MAX_RESPONSE_LEN)?;
+    read_until_match(r, |b| b == b'c', &mut buf, MAX_RESPONSE_LEN)?;
     Ok(())
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::recording::RecordedReply;
+    use crate::ErrorContext;
+
+    #[test]
+    fn accepts_bel_and_st_terminated_responses() {
+        let expected = Color::rgb(0xffff, 0xeeee, 0xdddd);
+        for response in [
+            b"\x1b]11;rgb:ffff/eeee/dddd\x07".to_vec(),
+            b"\x1b]11;rgb:ffff/eeee/dddd\x1b\\".to_vec(),
+        ] {
+            assert_eq!(
+                expected,
+                parse_response(response, BG_RESPONSE_PREFIX).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_unterminated_response() {
+        let response = b"\x1b]11;rgb:ffff/eeee/dddd".to_vec();
+        assert!(matches!(
+            parse_response(response, BG_RESPONSE_PREFIX),
+            Err(Error::InvalidResponse(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_endless_response() {
+        let mut input = b"\x1b]11;rgb:".to_vec();
+        input.resize(4 * MAX_RESPONSE_LEN, b'f');
+        let error = read_response(&mut input.as_slice()).unwrap_err();
+        assert!(matches!(error, Error::InvalidResponse(e) if e.response.len() == MAX_RESPONSE_LEN));
+    }
+
+    #[test]
+    fn rejects_endless_stream_of_responses() {
+        let input = b"\x1b[8;24;80t".repeat(MAX_RESPONSES_LEN);
+        assert!(matches!(
+            read_responses_until_da1(&mut input.as_slice(), TerminalQuirks::default()),
+            Err(Error::InvalidResponse(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_garbage_instead_of_response() {
+        let mut input: &[u8] = b"hello\x1b]11;rgb:0/0/0\x07";
+        assert!(matches!(
+            read_response(&mut input),
+            Err(Error::InvalidResponse(e)) if e.response == b"h"
+        ));
+    }
+
+    #[test]
+    fn consumes_late_responses_starting_mid_reply() {
+        let mut input: &[u8] = b"eeee/dddd\x1b\\\x1b]10;rgb:0/0/0\x07\x1b[?62c\x1b[6;20;10t";
+        consume_responses_until_da1(&mut input).unwrap();
+        assert_eq!(b"\x1b[6;20;10t", input);
+    }
+
+    #[test]
+    fn collects_responses_until_da1() {
+        let mut input: &[u8] = b"\x1b[4;480;800t\x1b[8;24;80t\x1b[?62;22c\x1b[6;20;10t";
+        let responses = read_responses_until_da1(&mut input, TerminalQuirks::default()).unwrap();
+        assert_eq!(
+            vec![b"\x1b[4;480;800t".to_vec(), b"\x1b[8;24;80t".to_vec()],
+            responses
+        );
+        assert_eq!(b"\x1b[6;20;10t", input);
+    }
+
+    #[test]
+    fn collects_osc_responses_with_both_terminators() {
+        let mut input: &[u8] = b"\x1b]10;rgb:0/0/0\x07\x1b]11;rgb:f/f/f\x1b\\\x1b[?62c";
+        let responses = read_responses_until_da1(&mut input, TerminalQuirks::default()).unwrap();
+        assert_eq!(
+            vec![
+                b"\x1b]10;rgb:0/0/0\x07".to_vec(),
+                b"\x1b]11;rgb:f/f/f\x1b\\".to_vec()
+            ],
+            responses
+        );
+    }
+
+    #[test]
+    fn parses_kitty_response() {
+        let responses = [b"\x1b]21;foreground=rgb:ff/ff/ff;background=rgb:00/00/00;cursor_text=;selection_background=rgb:ff/00/00;transparent_background_color2=rgb:00/00/ff;1=rgb:cc/00/00;255=rgb:ee/ee/ee\x1b\\".to_vec()];
+        let palette = parse_kitty_responses(&responses).unwrap();
+        assert_eq!(Color::rgb(u16::MAX, u16::MAX, u16::MAX), palette.foreground);
+        assert_eq!(Color::rgb(0, 0, 0), palette.background);
+        assert_eq!(None, palette.cursor_text);
+        assert_eq!(
+            Some(Color::rgb(u16::MAX, 0, 0)),
+            palette.selection_background
+        );
+        assert_eq!(
+            vec![Color::rgb(0, 0, u16::MAX)],
+            palette.transparent_backgrounds
+        );
+        assert_eq!(None, palette.ansi[0]);
+        assert_eq!(Some(Color::rgb(0xcccc, 0, 0)), palette.ansi[1]);
+        assert_eq!(Some(Color::rgb(0xeeee, 0xeeee, 0xeeee)), palette.ansi[255]);
+    }
+
+    #[test]
+    fn kitty_response_is_required_for_kitty_palette() {
+        let responses = [b"\x1b]11;rgb:0/0/0\x07".to_vec()];
+        assert_eq!(None, parse_kitty_responses(&responses));
+    }
+
+    #[test]
+    fn parses_osc_responses() {
+        let responses = [
+            b"\x1b]10;rgb:ffff/ffff/ffff\x07".to_vec(),
+            b"\x1b]11;rgb:0000/0000/0000\x07".to_vec(),
+            b"\x1b]12;rgb:ffff/0000/0000\x07".to_vec(),
+            b"\x1b]4;3;rgb:cdcd/cdcd/0000\x07".to_vec(),
+        ];
+        let palette = parse_osc_responses(&responses).unwrap();
+        assert_eq!(Color::rgb(u16::MAX, u16::MAX, u16::MAX), palette.foreground);
+        assert_eq!(Some(Color::rgb(u16::MAX, 0, 0)), palette.cursor);
+        assert_eq!(None, palette.selection_background);
+        assert_eq!(Some(Color::rgb(0xcdcd, 0xcdcd, 0)), palette.ansi[3]);
+        assert_eq!(ANSI_PALETTE_LEN, palette.ansi.len());
+    }
+
+    #[test]
+    fn osc_responses_without_background_are_unsupported() {
+        let responses = [b"\x1b]10;rgb:ffff/ffff/ffff\x07".to_vec()];
+        assert_eq!(None, parse_osc_responses(&responses));
+    }
+
+    #[test]
+    fn parses_color_scheme_response() {
+        assert_eq!(
+            Some(ThemeMode::Dark),
+            parse_color_scheme_response(b"\x1b[?997;1n")
+        );
+        assert_eq!(
+            Some(ThemeMode::Light),
+            parse_color_scheme_response(b"\x1b[?997;2n")
+        );
+        assert_eq!(None, parse_color_scheme_response(b"\x1b[?997;3n"));
+        assert_eq!(None, parse_color_scheme_response(b"\x1b[?62;22c"));
+    }
+
+    #[test]
+    fn prefers_reported_theme_mode_and_detects_disagreement() {
+        let responses = [
+            b"\x1b]10;rgb:0000/0000/0000\x07".to_vec(),
+            b"\x1b]11;rgb:ffff/ffff/ffff\x07".to_vec(),
+            b"\x1b[?997;1n".to_vec(),
+        ];
+        let report =
+            parse_theme_mode_responses(&responses, TerminalQuirks::default(), Duration::ZERO)
+                .unwrap();
+        assert_eq!(ThemeMode::Dark, report.theme_mode);
+        assert_eq!(crate::ThemeModeSource::Terminal, report.source);
+        assert!(report.is_disagreement());
+    }
+
+    #[test]
+    fn reports_da1_sentinel_if_only_da1_is_answered() {
+        let error =
+            parse_theme_mode_responses(&[], TerminalQuirks::default(), Duration::ZERO).unwrap_err();
+        assert!(
+            matches!(&error, Error::UnsupportedTerminal(e) if e.reason == UnsupportedReason::Da1Sentinel)
+        );
+
+        let responses = [b"\x1b]10;rgb:ffff/ffff/ffff\x07".to_vec()];
+        let error =
+            parse_theme_mode_responses(&responses, TerminalQuirks::default(), Duration::ZERO)
+                .unwrap_err();
+        assert!(
+            matches!(&error, Error::UnsupportedTerminal(e) if e.reason == UnsupportedReason::MissingColors)
+        );
+    }
+
+    #[test]
+    fn parses_geometry_with_missing_fields() {
+        let responses = [b"\x1b[4;480;800t".to_vec(), b"\x1b[8;24;80t".to_vec()];
+        let geometry = parse_geometry_responses(&responses);
+        assert_eq!(
+            Some(Size {
+                width: 800,
+                height: 480
+            }),
+            geometry.text_area_pixels
+        );
+        assert_eq!(None, geometry.cell_pixels);
+        assert_eq!(
+            Some(Size {
+                width: 80,
+                height: 24
+            }),
+            geometry.text_area_cells
+        );
+    }
+
+    #[test]
+    fn ignores_zero_sized_geometry() {
+        let responses = [b"\x1b[4;0;0t".to_vec(), b"\x1b[6;20;10t".to_vec()];
+        let geometry = parse_geometry_responses(&responses);
+        assert_eq!(None, geometry.text_area_pixels);
+        assert_eq!(
+            Some(Size {
+                width: 10,
+                height: 20
+            }),
+            geometry.cell_pixels
+        );
+    }
+
+    fn recording(replies: Vec<RecordedReply>) -> Recording {
+        let quirks = TerminalQuirks::default();
+        let mut query = Vec::new();
+        write_query(&mut query, quirks, QUERY_FG).unwrap();
+        write_query(&mut query, quirks, QUERY_BG).unwrap();
+        query.extend_from_slice(DA1);
+        Recording {
+            quirks,
+            query,
+            replies,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn reports_da1_sentinel_as_reason() {
+        let recording = recording(vec![RecordedReply::new(Duration::ZERO, "\x1b[?62;22c")]);
+        let error = replay_color_palette(&recording).unwrap_err();
+        let Error::UnsupportedTerminal(e) = &error else {
+            panic!("expected unsupported terminal, got {error}");
+        };
+        assert_eq!(UnsupportedReason::Da1Sentinel, e.reason);
+        assert_eq!(Some(QueryKind::Foreground), error.context().query);
+        assert_eq!(Some(Phase::Read), error.context().phase);
+    }
+
+    #[test]
+    fn reports_partial_reply_on_timeout() {
+        let recording = recording(vec![RecordedReply::new(
+            Duration::ZERO,
+            "\x1b]10;rgb:0/0/0\x07\x1b]11;rgb:f",
+        )]);
+        let error = replay_color_palette(&recording).unwrap_err();
+        assert!(matches!(error, Error::Timeout(_)));
+        assert_eq!(Some(QueryKind::Background), error.context().query);
+        assert_eq!(Some(Phase::Read), error.context().phase);
+        assert_eq!(
+            b"\x1b]10;rgb:0/0/0\x07\x1b]11;rgb:f",
+            error.context().received.as_slice()
+        );
+    }
+
+    #[test]
+    fn reports_query_that_failed_to_parse() {
+        let recording = recording(vec![RecordedReply::new(
+            Duration::ZERO,
+            "\x1b]10;rgb:0/0/0\x07\x1b]11;rgb:nope\x07\x1b[?62;22c",
+        )]);
+        let error = replay_color_palette(&recording).unwrap_err();
+        assert!(matches!(&error, Error::Parse(e) if e.response == b"\x1b]11;rgb:nope\x07"));
+        assert_eq!(Some(QueryKind::Background), error.context().query);
+        assert_eq!(Some(Phase::Parse), error.context().phase);
+        assert_eq!(
+            "failed to parse response: ^[]11;rgb:nope^G while parsing the reply (query: background color)",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn reports_latency_of_the_query() {
+        let recording = recording(vec![
+            RecordedReply::new(
+                Duration::from_millis(3),
+                "\x1b]10;rgb:0/0/0\x07\x1b]11;rgb:f/f/f\x07",
+            ),
+            RecordedReply::new(Duration::from_millis(5), "\x1b[?62;22c"),
+        ]);
+        let (_, latency) = color_palette_with(
+            &QueryOptions::default(),
+            recording.quirks,
+            Replay {
+                recording: &recording,
+            },
+        )
+        .unwrap();
+        assert_eq!(Duration::from_millis(5), latency);
+    }
+
+    #[test]
+    fn extends_adaptive_timeout_for_slow_terminals() {
+        // The reply is split across the short timeout.
+        let recording = recording(vec![
+            RecordedReply::new(Duration::from_millis(150), "\x1b]10;rgb:0/0/0\x07\x1b]11;"),
+            RecordedReply::new(Duration::from_millis(300), "rgb:f/f/f\x07\x1b[?62;22c"),
+        ]);
+        let options = QueryOptions {
+            adaptive_timeout: true,
+            ..Default::default()
+        };
+        let (palette, latency) = color_palette_with(
+            &options,
+            recording.quirks,
+            Replay {
+                recording: &recording,
+            },
+        )
+        .unwrap();
+        assert_eq!(ThemeMode::Light, palette.theme_mode());
+        assert_eq!(Duration::from_millis(300), latency);
+    }
+
+    #[test]
+    fn records_the_exchange() {
+        let recording = recording(vec![
+            RecordedReply::new(Duration::ZERO, "\x1b]10;rgb:0/0/0\x07"),
+            RecordedReply::new(Duration::ZERO, "\x1b]11;rgb:f/f/f\x07\x1b[?62;22c"),
+        ]);
+        let mut rerecording = Recording::default();
+        let report = theme_mode_report_with(
+            &QueryOptions::default(),
+            recording.quirks,
+            Record::new(
+                Replay {
+                    recording: &recording,
+                },
+                &mut rerecording,
+            ),
+        )
+        .unwrap();
+        assert_eq!(ThemeMode::Light, report.theme_mode);
+        assert_eq!(recording.query, rerecording.query);
+        let received = |r: &Recording| {
+            r.replies
+                .iter()
+                .flat_map(|r| r.bytes.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(received(&recording), received(&rerecording));
+    }
+
+    #[test]
+    fn reports_palette_index_on_timeout() {
+        let quirks = TerminalQuirks::default();
+        let mut query = Vec::new();
+        for (prefix, _) in osc_palette_replies() {
+            write_query(&mut query, quirks, &[&prefix, &b"?"[..]].concat()).unwrap();
+        }
+        query.extend_from_slice(DA1);
+        // The terminal doesn't support the selection colors and stalls after color 1.
+        let recording = Recording {
+            quirks,
+            query,
+            replies: vec![RecordedReply::new(
+                Duration::ZERO,
+                "\x1b]10;rgb:0/0/0\x07\x1b]11;rgb:f/f/f\x07\x1b]12;rgb:0/0/0\x07\
+                 \x1b]4;0;rgb:0/0/0\x07\x1b]4;1;rgb:c/0/0\x07\x1b]4;2;rgb:0/c",
+            )],
+            ..Default::default()
+        };
+        let error = osc_color_palette_with(
+            &QueryOptions::default(),
+            quirks,
+            Replay {
+                recording: &recording,
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(error, Error::Timeout(_)));
+        assert_eq!(Some(QueryKind::Palette(2)), error.context().query);
+        assert_eq!(
+            "operation did not complete within 1s while reading the reply (query: palette color 2)",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn reports_missing_color() {
+        let responses = [b"\x1b]10;rgb:ffff/ffff/ffff\x07".to_vec()];
+        assert_eq!(
+            Some(QueryKind::Background),
+            missing_colors(&responses).context().query
+        );
+        assert_eq!(
+            Some(QueryKind::Foreground),
+            missing_colors(&[]).context().query
+        );
+    }
+
+    #[test]
+    fn known_unsupported_terminals_are_not_queried() {
+        let recording = Recording {
+            quirks: TerminalQuirks {
+                unsupported: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let error = replay_color_palette(&recording).unwrap_err();
+        assert!(
+            matches!(&error, Error::UnsupportedTerminal(e) if e.reason == UnsupportedReason::Quirks)
+        );
+        assert_eq!(ErrorContext::default(), *error.context());
+    }
+}
@@ -0,0 +1,196 @@
+//! A fake terminal for testing the exchange with the terminal end-to-end.
+//!
+//! The query runs in a child process (the test binary re-executing one of its ignored tests)
+//! whose standard input and standard error are connected to the user side of a pseudo terminal.
+//! The test plays the terminal on the controlling side of the pseudo terminal.
+//! The child reports the query's result on its standard output, which is a pipe.
+
+#![allow(dead_code, clippy::unwrap_used, clippy::use_debug)]
+
+use libc::{
+    c_int, grantpt, poll, pollfd, posix_openpt, tcgetattr, termios, unlockpt, ECHO, ICANON,
+    O_CLOEXEC, O_NOCTTY, O_RDWR, POLLIN,
+};
+use std::ffi::{CStr, OsStr};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read as _, Write as _};
+use std::mem::MaybeUninit;
+use std::os::fd::{AsRawFd as _, FromRawFd as _, OwnedFd};
+use std::os::unix::ffi::OsStrExt as _;
+use std::os::unix::fs::OpenOptionsExt as _;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+use terminal_colorsaurus::QueryOptions;
+
+const CHILD_QUERY_VAR: &str = "FAKE_TERMINAL_QUERY";
+const CHILD_TIMEOUT_VAR: &str = "FAKE_TERMINAL_TIMEOUT_MS";
+const CHILD_GRACE_PERIOD_VAR: &str = "FAKE_TERMINAL_GRACE_PERIOD_MS";
+const RESULT_PREFIX: &str = "RESULT: ";
+
+/// The controlling side of a pseudo terminal.
+pub struct FakeTerminal {
+    controlling: File,
+    user: OwnedFd,
+}
+
+impl FakeTerminal {
+    pub fn new() -> io::Result<Self> {
+        // SAFETY: We check that the file descriptor is valid (not -1).
+        let fd = to_io_result(unsafe { posix_openpt(O_RDWR | O_NOCTTY | O_CLOEXEC) })?;
+        // SAFETY: posix_openpt creates a new fd for us.
+        let controlling = unsafe { File::from_raw_fd(fd) };
+        // SAFETY: We just created the fd, so we know it's valid.
+        to_io_result(unsafe { grantpt(fd) })?;
+        // SAFETY: We just created the fd, so we know it's valid.
+        to_io_result(unsafe { unlockpt(fd) })?;
+        let user = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(O_NOCTTY)
+            .open(OsStr::from_bytes(ptsname(&controlling)?.to_bytes()))?
+            .into();
+        Ok(Self { controlling, user })
+    }
+
+    /// Spawns a child process that runs `query` (see [`run_child`]) on this terminal.
+    pub fn spawn(
+        &self,
+        child_test: &str,
+        query: &str,
+        options: &QueryOptions,
+    ) -> io::Result<Child> {
+        Command::new(std::env::current_exe()?)
+            .args([
+                child_test,
+                "--exact",
+                "--ignored",
+                "--nocapture",
+                "--test-threads=1",
+            ])
+            .env(CHILD_QUERY_VAR, query)
+            .env(CHILD_TIMEOUT_VAR, options.timeout.as_millis().to_string())
+            .env(
+                CHILD_GRACE_PERIOD_VAR,
+                options.grace_period.as_millis().to_string(),
+            )
+            .env("TERM", "xterm-256color")
+            .env_remove("TERM_PROGRAM")
+            .env_remove("SSH_TTY")
+            .env_remove("SSH_CONNECTION")
+            .stdin(Stdio::from(self.user.try_clone()?))
+            .stderr(Stdio::from(self.user.try_clone()?))
+            .stdout(Stdio::piped())
+            .spawn()
+    }
+
+    /// Reads from the terminal until the data ends with `suffix`.
+    pub fn read_until(&mut self, suffix: &[u8], timeout: Duration) -> io::Result<Vec<u8>> {
+        let deadline = Instant::now() + timeout;
+        let mut data = Vec::new();
+        while !data.ends_with(suffix) {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if !self.poll_read(remaining)? {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("expected {suffix:?}, got {data:?}"),
+                ));
+            }
+            let mut byte = [0];
+            self.controlling.read_exact(&mut byte)?;
+            data.push(byte[0]);
+        }
+        Ok(data)
+    }
+
+    /// Reads everything that arrives within `duration`.
+    pub fn read_for(&mut self, duration: Duration) -> io::Result<Vec<u8>> {
+        let deadline = Instant::now() + duration;
+        let mut data = Vec::new();
+        let mut buf = [0; 256];
+        while self.poll_read(deadline.saturating_duration_since(Instant::now()))? {
+            let read = self.controlling.read(&mut buf)?;
+            data.extend_from_slice(&buf[..read]);
+        }
+        Ok(data)
+    }
+
+    pub fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.controlling.write_all(bytes)?;
+        self.controlling.flush()
+    }
+
+    /// Returns `true` if the terminal is in raw mode (i.e. neither in canonical mode nor echoing).
+    pub fn is_raw(&self) -> io::Result<bool> {
+        let mut termios = MaybeUninit::<termios>::uninit();
+        // SAFETY: We pass a valid pointer to a termios struct which tcgetattr fills in on success.
+        to_io_result(unsafe { tcgetattr(self.user.as_raw_fd(), termios.as_mut_ptr()) })?;
+        // SAFETY: tcgetattr succeeded, so the struct is initialized.
+        let local_modes = unsafe { termios.assume_init() }.c_lflag;
+        Ok(local_modes & (ICANON | ECHO) == 0)
+    }
+
+    fn poll_read(&self, timeout: Duration) -> io::Result<bool> {
+        let mut fds = [pollfd {
+            fd: self.controlling.as_raw_fd(),
+            events: POLLIN,
+            revents: 0,
+        }];
+        let timeout = c_int::try_from(timeout.as_millis()).unwrap_or(c_int::MAX);
+        // SAFETY: We pass a valid pointer to exactly one pollfd.
+        let ready = to_io_result(unsafe { poll(fds.as_mut_ptr(), 1, timeout) })?;
+        Ok(ready > 0)
+    }
+}
+
+/// Waits for the child to exit and returns the query result it reported.
+pub fn child_result(child: Child) -> String {
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // libtest prints the test's name on the same line before running it.
+    stdout
+        .lines()
+        .find_map(|line| line.split_once(RESULT_PREFIX).map(|(_, result)| result))
+        .unwrap_or_else(|| panic!("child did not report a result: {stdout}"))
+        .to_owned()
+}
+
+/// Runs the query requested by the parent and reports its result.
+/// Does nothing when not running as a child of [`FakeTerminal::spawn`].
+pub fn run_child() {
+    let Ok(query) = std::env::var(CHILD_QUERY_VAR) else {
+        return;
+    };
+    let mut options = QueryOptions::default();
+    options.timeout = duration_from_env(CHILD_TIMEOUT_VAR);
+    options.grace_period = duration_from_env(CHILD_GRACE_PERIOD_VAR);
+    let result = match query.as_str() {
+        "color_palette" => format!("{:?}", terminal_colorsaurus::color_palette(options)),
+        "foreground_color" => format!("{:?}", terminal_colorsaurus::foreground_color(options)),
+        "background_color" => format!("{:?}", terminal_colorsaurus::background_color(options)),
+        query => panic!("unknown query {query}"),
+    };
+    println!("{RESULT_PREFIX}{result}");
+}
+
+fn duration_from_env(var: &str) -> Duration {
+    Duration::from_millis(std::env::var(var).unwrap().parse().unwrap())
+}
+
+fn ptsname(controlling: &File) -> io::Result<std::ffi::CString> {
+    let mut buf = vec![0; 256];
+    // SAFETY: We pass the length of our buffer to ptsname_r.
+    let code = unsafe { libc::ptsname_r(controlling.as_raw_fd(), buf.as_mut_ptr(), buf.len()) };
+    if code != 0 {
+        return Err(io::Error::from_raw_os_error(code));
+    }
+    // SAFETY: ptsname_r writes a null-terminated string into our buffer on success.
+    Ok(unsafe { CStr::from_ptr(buf.as_ptr()) }.to_owned())
+}
+
+fn to_io_result(value: c_int) -> io::Result<c_int> {
+    if value == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(value)
+    }
+}
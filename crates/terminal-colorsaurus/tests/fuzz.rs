@@ -0,0 +1,155 @@
+//! Property tests for the response state machine.
+//! Replies can be forged by anything that can write to the terminal,
+//! so reading and parsing them needs to cope with arbitrary input.
+//!
+//! The replies are fed through the query code using [`recording`](terminal_colorsaurus::recording),
+//! seeded from the fixtures in `tests/fixtures`.
+#![allow(clippy::unwrap_used)]
+
+use proptest::prelude::*;
+use proptest::sample::{select, Index};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use terminal_colorsaurus::recording::{replay_color_palette, RecordedReply, Recording};
+
+// Tracks the peak heap usage of the current thread.
+struct PeakAlloc;
+
+thread_local! {
+    static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+    static PEAK: Cell<usize> = const { Cell::new(0) };
+}
+
+// SAFETY: We forward to the system allocator and only do bookkeeping on top.
+unsafe impl GlobalAlloc for PeakAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let allocated = ALLOCATED.get() + layout.size();
+        ALLOCATED.set(allocated);
+        PEAK.set(PEAK.get().max(allocated));
+        // SAFETY: Same contract as our caller.
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.set(ALLOCATED.get().saturating_sub(layout.size()));
+        // SAFETY: Same contract as our caller.
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: PeakAlloc = PeakAlloc;
+
+/// Returns the heap usage on top of what was allocated before calling `f`.
+fn peak_allocation<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATED.get();
+    PEAK.set(before);
+    let result = f();
+    (result, PEAK.get() - before)
+}
+
+fn corpus() -> Vec<Recording> {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| Recording::parse(&fs::read_to_string(entry.unwrap().path()).unwrap()).unwrap())
+        .collect()
+}
+
+// A fixture whose replies have been split at arbitrary positions and mutated.
+fn mutated_recording() -> impl Strategy<Value = Recording> {
+    (
+        select(corpus()),
+        prop::collection::vec((any::<Index>(), any::<u8>(), 0..3u8), 0..8),
+        prop::collection::vec(any::<Index>(), 0..4),
+    )
+        .prop_map(|(mut recording, mutations, splits)| {
+            let mut bytes: Vec<u8> = recording
+                .replies
+                .iter()
+                .flat_map(|r| r.bytes.clone())
+                .collect();
+            for (index, byte, kind) in mutations {
+                let position = index.index(bytes.len() + 1);
+                match kind {
+                    0 if position < bytes.len() => bytes[position] = byte,
+                    1 => bytes.insert(position, byte),
+                    _ if position < bytes.len() => _ = bytes.remove(position),
+                    _ => {}
+                }
+            }
+            recording.replies = chunks(bytes, splits);
+            recording
+        })
+}
+
+fn arbitrary_recording() -> impl Strategy<Value = Recording> {
+    (
+        select(corpus()),
+        prop::collection::vec(any::<u8>(), 0..256),
+        prop::collection::vec(any::<Index>(), 0..4),
+    )
+        .prop_map(|(mut recording, bytes, splits)| {
+            recording.replies = chunks(bytes, splits);
+            recording
+        })
+}
+
+fn chunks(bytes: Vec<u8>, splits: Vec<Index>) -> Vec<RecordedReply> {
+    let mut splits: Vec<_> = splits.iter().map(|s| s.index(bytes.len() + 1)).collect();
+    splits.push(0);
+    splits.push(bytes.len());
+    splits.sort_unstable();
+    // An empty reply would be replayed as the end of the input.
+    splits.dedup();
+    splits
+        .windows(2)
+        .enumerate()
+        .map(|(i, w)| {
+            let elapsed = Duration::from_micros(100 * (i as u64 + 1));
+            RecordedReply::new(elapsed, &bytes[w[0]..w[1]])
+        })
+        .collect()
+}
+
+fn reply_len(recording: &Recording) -> usize {
+    recording.replies.iter().map(|r| r.bytes.len()).sum()
+}
+
+// Generous upper bound: allocations may only grow linearly with the input.
+fn allocation_limit(recording: &Recording) -> usize {
+    4 * reply_len(recording) + 16 * 1024
+}
+
+proptest! {
+    #[test]
+    fn mutated_replies_do_not_panic(recording in mutated_recording()) {
+        let (_, allocated) = peak_allocation(|| replay_color_palette(&recording));
+        prop_assert!(allocated <= allocation_limit(&recording), "allocated {allocated} bytes");
+    }
+
+    #[test]
+    fn arbitrary_replies_do_not_panic(recording in arbitrary_recording()) {
+        let (_, allocated) = peak_allocation(|| replay_color_palette(&recording));
+        prop_assert!(allocated <= allocation_limit(&recording), "allocated {allocated} bytes");
+    }
+
+    #[test]
+    fn split_replies_are_parsed_the_same(
+        recording in select(corpus()),
+        splits in prop::collection::vec(any::<Index>(), 0..8),
+    ) {
+        let bytes = recording.replies.iter().flat_map(|r| r.bytes.clone()).collect();
+        let mut split = recording.clone();
+        split.replies = chunks(bytes, splits);
+        // Fixtures with replies after the timeout can't be compared as we move all replies forward in time.
+        prop_assume!(recording.replies.iter().all(|r| r.elapsed < recording.timeout));
+        prop_assert_eq!(
+            format!("{:?}", replay_color_palette(&recording)),
+            format!("{:?}", replay_color_palette(&split))
+        );
+    }
+}
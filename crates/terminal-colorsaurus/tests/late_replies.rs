@@ -0,0 +1,52 @@
+//! Tests that replies arriving after the timeout don't end up on the screen.
+#![cfg(target_os = "linux")]
+#![allow(clippy::unwrap_used)]
+
+mod fake_terminal;
+
+use fake_terminal::{child_result, FakeTerminal};
+use std::thread::sleep;
+use std::time::Duration;
+use terminal_colorsaurus::QueryOptions;
+
+const CHILD: &str = "child";
+const QUERY: &[u8] = b"\x1b]10;?\x07\x1b]11;?\x07\x1b[c";
+const REPLY: &[u8] = b"\x1b]10;rgb:ffff/ffff/ffff\x07\x1b]11;rgb:0000/0000/0000\x07\x1b[?62;22c";
+const TIMEOUT: Duration = Duration::from_millis(100);
+const REPLY_DELAY: Duration = Duration::from_millis(400);
+
+#[test]
+#[ignore = "run by the fake terminal in a child process"]
+fn child() {
+    fake_terminal::run_child();
+}
+
+fn query_slow_terminal(grace_period: Duration) -> (String, Vec<u8>) {
+    let mut terminal = FakeTerminal::new().unwrap();
+    let mut options = QueryOptions::default();
+    options.timeout = TIMEOUT;
+    options.grace_period = grace_period;
+    let child = terminal.spawn(CHILD, "color_palette", &options).unwrap();
+
+    terminal.read_until(QUERY, Duration::from_secs(5)).unwrap();
+    sleep(REPLY_DELAY);
+    terminal.write_all(REPLY).unwrap();
+
+    // Once the terminal is no longer in raw mode, the replies are echoed back to the screen.
+    let echoed = terminal.read_for(Duration::from_millis(300)).unwrap();
+    (child_result(child), echoed)
+}
+
+#[test]
+fn late_replies_are_echoed_without_grace_period() {
+    let (result, echoed) = query_slow_terminal(Duration::ZERO);
+    assert!(result.starts_with("Err(Timeout("), "{result}");
+    assert!(!echoed.is_empty());
+}
+
+#[test]
+fn late_replies_are_consumed_during_grace_period() {
+    let (result, echoed) = query_slow_terminal(Duration::from_secs(2));
+    assert!(result.starts_with("Err(Timeout("), "{result}");
+    assert_eq!("", String::from_utf8_lossy(&echoed));
+}
@@ -0,0 +1,164 @@
+//! Runs the queries against scripted fake terminals that mimic the behaviour of real terminals.
+#![cfg(target_os = "linux")]
+#![allow(clippy::unwrap_used)]
+
+mod fake_terminal;
+
+use fake_terminal::{child_result, FakeTerminal};
+use std::thread::sleep;
+use std::time::Duration;
+use terminal_colorsaurus::{Color, QueryOptions};
+
+const CHILD: &str = "child";
+const DA1_QUERY: &[u8] = b"\x1b[c";
+const DA1_REPLY: &[u8] = b"\x1b[?62;22c";
+const FOREGROUND: &str = "rgb:dcdc/dcdc/cccc";
+const BACKGROUND: &str = "rgb:3f3f/3f3f/3f3f";
+const QUERIES: &[&str] = &["color_palette", "foreground_color", "background_color"];
+
+#[test]
+#[ignore = "run by the fake terminal in a child process"]
+fn child() {
+    fake_terminal::run_child();
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Personality {
+    /// Replies to `OSC` queries using `ST` (like xterm).
+    XtermSt,
+    /// Replies to `OSC` queries using `BEL` regardless of the query's terminator (like urxvt).
+    UrxvtBel,
+    /// Doesn't support querying colors, but answers `DA1`.
+    Da1Only,
+    /// Answers `DA1` right away and forwards the color replies of
+    /// the actual terminal later (like a terminal multiplexer such as screen).
+    ScreenReordering,
+    /// Takes a while to reply (e.g. because it's connected via SSH).
+    Slow,
+    /// Never answers anything.
+    Silent,
+}
+
+impl Personality {
+    fn reply(self, terminal: &mut FakeTerminal, query: &[u8]) {
+        let color_replies = |terminator: &str| {
+            let mut replies = Vec::new();
+            for (code, color) in [("10", FOREGROUND), ("11", BACKGROUND)] {
+                if contains(query, format!("\x1b]{code};?").as_bytes()) {
+                    replies
+                        .extend_from_slice(format!("\x1b]{code};{color}{terminator}").as_bytes());
+                }
+            }
+            replies
+        };
+        match self {
+            Personality::XtermSt => {
+                terminal.write_all(&color_replies("\x1b\\")).unwrap();
+                terminal.write_all(DA1_REPLY).unwrap();
+            }
+            Personality::UrxvtBel => {
+                terminal.write_all(&color_replies("\x07")).unwrap();
+                terminal.write_all(DA1_REPLY).unwrap();
+            }
+            Personality::Da1Only => terminal.write_all(DA1_REPLY).unwrap(),
+            Personality::ScreenReordering => {
+                terminal.write_all(DA1_REPLY).unwrap();
+                sleep(Duration::from_millis(50));
+                terminal.write_all(&color_replies("\x07")).unwrap();
+            }
+            Personality::Slow => {
+                sleep(Duration::from_millis(300));
+                terminal.write_all(&color_replies("\x1b\\")).unwrap();
+                terminal.write_all(DA1_REPLY).unwrap();
+            }
+            Personality::Silent => {}
+        }
+    }
+}
+
+struct Outcome {
+    result: String,
+    raw_after_exit: bool,
+}
+
+fn run(personality: Personality, query: &str) -> Outcome {
+    let mut terminal = FakeTerminal::new().unwrap();
+    let mut options = QueryOptions::default();
+    options.timeout = Duration::from_millis(200);
+    if let Personality::Slow = personality {
+        options.timeout = Duration::from_secs(2);
+    }
+    let child = terminal.spawn(CHILD, query, &options).unwrap();
+
+    let query = terminal
+        .read_until(DA1_QUERY, Duration::from_secs(5))
+        .unwrap();
+    personality.reply(&mut terminal, &query);
+
+    let result = child_result(child);
+    Outcome {
+        result,
+        raw_after_exit: terminal.is_raw().unwrap(),
+    }
+}
+
+fn assert_colors(personality: Personality) {
+    let foreground = format!("{:?}", Color::rgb(0xdcdc, 0xdcdc, 0xcccc));
+    let background = format!("{:?}", Color::rgb(0x3f3f, 0x3f3f, 0x3f3f));
+    let expected = [
+        format!("Ok(ColorPalette {{ foreground: {foreground}, background: {background} }})"),
+        format!("Ok({foreground})"),
+        format!("Ok({background})"),
+    ];
+    for (query, expected) in QUERIES.iter().zip(expected) {
+        let outcome = run(personality, query);
+        assert_eq!(expected, outcome.result, "{personality:?} {query}");
+        assert!(!outcome.raw_after_exit, "{personality:?} {query}");
+    }
+}
+
+fn assert_error(personality: Personality, expected_prefix: &str) {
+    for query in QUERIES {
+        let outcome = run(personality, query);
+        assert!(
+            outcome.result.starts_with(expected_prefix),
+            "{personality:?} {query}: {}",
+            outcome.result
+        );
+        assert!(!outcome.raw_after_exit, "{personality:?} {query}");
+    }
+}
+
+#[test]
+fn xterm_replying_with_st() {
+    assert_colors(Personality::XtermSt);
+}
+
+#[test]
+fn urxvt_replying_with_bel() {
+    assert_colors(Personality::UrxvtBel);
+}
+
+#[test]
+fn terminal_only_answering_da1_is_unsupported() {
+    assert_error(Personality::Da1Only, "Err(UnsupportedTerminal(");
+}
+
+#[test]
+fn terminal_answering_da1_first_is_unsupported() {
+    assert_error(Personality::ScreenReordering, "Err(UnsupportedTerminal(");
+}
+
+#[test]
+fn slow_terminal_within_timeout() {
+    assert_colors(Personality::Slow);
+}
+
+#[test]
+fn silent_terminal_times_out() {
+    assert_error(Personality::Silent, "Err(Timeout(");
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
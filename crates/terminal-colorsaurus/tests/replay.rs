@@ -0,0 +1,136 @@
+//! Replays the fixtures in `tests/fixtures` (see the `record` example).
+//!
+//! Fixtures marked with `# Synthetic:` were reconstructed from the terminal survey
+//! instead of being recorded from a real session. They are replayed like the others,
+//! but only recorded fixtures count as coverage of the survey.
+#![allow(clippy::unwrap_used)]
+
+use std::fs;
+use std::path::Path;
+use terminal_colorsaurus::recording::{replay_color_palette, Recording};
+use terminal_colorsaurus::{Error, ThemeMode};
+
+#[test]
+fn fixtures() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let mut count = 0;
+    for entry in fs::read_dir(directory).unwrap() {
+        let path = entry.unwrap().path();
+        let fixture = fs::read_to_string(&path).unwrap();
+        let recording = Recording::parse(&fixture)
+            .unwrap_or_else(|| panic!("{}: malformed fixture", path.display()));
+        let expected = fixture
+            .lines()
+            .find_map(|line| line.strip_prefix("# expected: "))
+            .unwrap_or_else(|| panic!("{}: missing expected result", path.display()));
+        let actual = match replay_color_palette(&recording) {
+            Ok(palette) => match palette.theme_mode() {
+                ThemeMode::Dark => "dark".to_owned(),
+                ThemeMode::Light => "light".to_owned(),
+            },
+            Err(Error::Timeout(_)) => "timeout".to_owned(),
+            Err(Error::UnsupportedTerminal(_)) => "unsupported".to_owned(),
+            Err(e) => format!("error: {e}"),
+        };
+        assert_eq!(expected, actual, "{}", path.display());
+        count += 1;
+    }
+    assert_ne!(0, count);
+}
+
+// Surveyed terminals that only have a synthetic fixture so far.
+// Remove a terminal from this list once its fixture is replaced by a recording.
+const AWAITING_RECORDING: &[&str] = &[
+    "alacritty",
+    "anyterm",
+    "conemu-cmder",
+    "contour",
+    "cool-retro-term",
+    "eterm",
+    "extraterm",
+    "foot",
+    "ghostty",
+    "gnome-console",
+    "hyper",
+    "intellij-idea-jediterm",
+    "ish-hterm",
+    "iterm2",
+    "jetbrains-fleet",
+    "kitty",
+    "konsole",
+    "la-terminal",
+    "lapce",
+    "linux-console",
+    "macos-terminal",
+    "mintty",
+    "mlterm",
+    "mobaxterm",
+    "mosh",
+    "mrxvt",
+    "neovim",
+    "pangoterm",
+    "putty",
+    "qmlkonsole",
+    "qterminal",
+    "rio",
+    "rxvt-unicode",
+    "shellinabox",
+    "terminology",
+    "termux",
+    "therm",
+    "vscode-xterm-js",
+    "warp",
+    "wayst",
+    "wezterm",
+    "windows-terminal-conhost",
+    "xterm",
+    "yakuake",
+    "zed",
+    "zutty",
+];
+
+#[test]
+fn every_surveyed_terminal_is_recorded() {
+    let survey = include_str!("../doc/terminal-survey.md");
+    let rows = survey
+        .lines()
+        .skip_while(|line| !line.starts_with("| Terminal "))
+        .skip(2)
+        .take_while(|line| line.starts_with('|'));
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let mut count = 0;
+    for row in rows {
+        let terminal = row.split('|').nth(1).unwrap();
+        let name = fixture_name(terminal);
+        let path = directory.join(format!("{name}.txt"));
+        let recorded = fs::read_to_string(&path)
+            .is_ok_and(|fixture| !fixture.lines().any(|l| l.starts_with("# Synthetic:")));
+        if AWAITING_RECORDING.contains(&name.as_str()) {
+            assert!(!recorded, "{terminal}: remove it from AWAITING_RECORDING");
+        } else {
+            assert!(recorded, "{terminal}: missing recording {}", path.display());
+        }
+        count += 1;
+    }
+    assert_ne!(0, count);
+}
+
+// `(GNOME) [Console] [^1]` becomes `gnome-console`.
+fn fixture_name(terminal: &str) -> String {
+    let mut terminal = terminal.to_owned();
+    // Footnotes (`[^1]`) and link references (`[neovim][nvim-terminal]`) are not part of the name.
+    for (pattern, offset) in [("[^", 0), ("][", 1)] {
+        while let Some(start) = terminal.find(pattern).map(|i| i + offset) {
+            let end = terminal[start..]
+                .find(']')
+                .map_or(terminal.len(), |i| start + i + 1);
+            terminal.replace_range(start..end, "");
+        }
+    }
+    terminal
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_ascii_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
+}
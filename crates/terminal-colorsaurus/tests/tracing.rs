@@ -0,0 +1,74 @@
+//! Checks the instrumentation enabled by the `tracing` feature.
+#![cfg(feature = "tracing")]
+#![allow(clippy::unwrap_used)]
+
+use std::fmt::{self, Write as _};
+use std::sync::{Arc, Mutex};
+use terminal_colorsaurus::recording::{self, Recording};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+#[test]
+fn logs_exchange_with_terminal() {
+    let fixture = include_str!("fixtures/xterm.txt");
+    let recording = Recording::parse(fixture).unwrap();
+    let collector = Collector::default();
+    tracing::subscriber::with_default(collector.clone(), || {
+        recording::replay_color_palette(&recording).unwrap();
+    });
+    let log = collector.log.lock().unwrap();
+    assert!(log.contains("span query"), "{log}");
+    assert!(log.contains("writing query query=^[]10;?"), "{log}");
+    assert!(log.contains("read from terminal bytes=^[]10;rgb:"), "{log}");
+    assert!(log.contains("parsed response response=^[]11;rgb:"), "{log}");
+}
+
+// A minimal subscriber that writes events and spans to a string.
+#[derive(Clone, Default)]
+struct Collector {
+    log: Arc<Mutex<String>>,
+}
+
+impl Subscriber for Collector {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut log = self.log.lock().unwrap();
+        writeln!(log, "span {}", span.metadata().name()).unwrap();
+        Id::from_u64(1)
+    }
+
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut line = String::new();
+        event.record(&mut Line(&mut line));
+        writeln!(self.log.lock().unwrap(), "{}", line.trim()).unwrap();
+    }
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+struct Line<'a>(&'a mut String);
+
+impl Visit for Line<'_> {
+    #[allow(clippy::use_debug)]
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.0.insert_str(0, &format!("{value:?} "));
+        } else {
+            write!(self.0, "{}={value:?} ", field.name()).unwrap();
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record_debug(field, &format_args!("{value}"));
+    }
+}
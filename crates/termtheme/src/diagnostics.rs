@@ -0,0 +1,546 @@
+use crate::display_help;
+use crate::format::{DisplayName, Hex};
+use serde::Serialize;
+use std::env;
+use std::fmt::{self, Write as _};
+use std::io::{stdout, IsTerminal as _};
+use std::time::Duration;
+use terminal_colorsaurus::quirks::{
+    terminal_quirks, Passthrough, StringTerminator, TerminalQuirks,
+};
+use terminal_colorsaurus::recording::Recording;
+use terminal_colorsaurus::{
+    should_query, ColorPalette, Error, ExtendedColorPalette, QueryDecision, QueryOptions, Result,
+    ThemeMode, ThemeModeReport, UnsupportedReason,
+};
+
+/// The flags that ask for diagnostics. They apply to every command that queries the terminal.
+#[derive(clap::Args, Debug, Clone, Copy, Default)]
+pub(crate) struct DiagnosticsArgs {
+    /// Print diagnostics to stderr: the terminal, its quirks, the reply timings and hints.
+    ///
+    /// Applies to the detection and to every command that queries the terminal
+    /// ('watch' only diagnoses the first query).
+    #[arg(short, long, global = true)]
+    verbose: bool,
+    /// Like '--verbose' but also print the bytes sent to and received from the terminal.
+    #[arg(long, global = true)]
+    debug: bool,
+    /// Print the diagnostics as JSON, e.g. for bug reports. Implies '--debug'.
+    #[arg(long, global = true)]
+    pub(crate) json: bool,
+}
+
+impl DiagnosticsArgs {
+    pub(crate) fn verbosity(&self) -> Option<Verbosity> {
+        if self.debug || self.json {
+            Some(Verbosity::Debug)
+        } else if self.verbose {
+            Some(Verbosity::Verbose)
+        } else {
+            None
+        }
+    }
+
+    /// The first flag that was passed, if any.
+    pub(crate) fn used_flag(&self) -> Option<&'static str> {
+        [
+            (self.verbose, "--verbose"),
+            (self.debug, "--debug"),
+            (self.json, "--json"),
+        ]
+        .into_iter()
+        .find_map(|(used, flag)| used.then_some(flag))
+    }
+
+    /// Runs `query` or, if diagnostics were requested, `record`
+    /// and prints the diagnostics before returning the result.
+    pub(crate) fn query<T: QueryOutcome>(
+        &self,
+        options: QueryOptions,
+        query: fn(QueryOptions) -> Result<T>,
+        record: fn(QueryOptions) -> (Result<T>, Recording),
+    ) -> Result<T> {
+        let Some(verbosity) = self.verbosity() else {
+            return query(options);
+        };
+        let mut diagnostics =
+            Diagnostics::new(verbosity, terminal_quirks(), stdout().is_terminal());
+        let (result, recording) = record(options);
+        diagnostics.record(&result, &recording);
+        diagnostics.print(self.json);
+        result
+    }
+}
+
+/// What a query found out about the theme.
+pub(crate) trait QueryOutcome {
+    fn theme_mode(&self) -> ThemeMode;
+
+    fn color_palette(&self) -> Option<ColorPalette>;
+}
+
+impl QueryOutcome for ColorPalette {
+    fn theme_mode(&self) -> ThemeMode {
+        ColorPalette::theme_mode(self)
+    }
+
+    fn color_palette(&self) -> Option<ColorPalette> {
+        Some(self.clone())
+    }
+}
+
+impl QueryOutcome for ExtendedColorPalette {
+    fn theme_mode(&self) -> ThemeMode {
+        ExtendedColorPalette::theme_mode(self)
+    }
+
+    fn color_palette(&self) -> Option<ColorPalette> {
+        Some(ExtendedColorPalette::color_palette(self))
+    }
+}
+
+impl QueryOutcome for ThemeModeReport {
+    fn theme_mode(&self) -> ThemeMode {
+        self.theme_mode
+    }
+
+    fn color_palette(&self) -> Option<ColorPalette> {
+        self.palette.clone()
+    }
+}
+
+/// How much to tell about the exchange with the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Verbosity {
+    Verbose,
+    /// Also includes the raw bytes sent and received.
+    Debug,
+}
+
+/// Everything that helps with figuring out why detecting the theme failed.
+#[derive(Serialize)]
+pub(crate) struct Diagnostics {
+    env: Env,
+    quirks: Quirks,
+    stdout_is_terminal: bool,
+    /// Why querying is a bad idea right now, if it is.
+    skip_reason: Option<String>,
+    query: Option<Query>,
+    hints: Vec<String>,
+    #[serde(skip)]
+    verbosity: Verbosity,
+}
+
+#[derive(Serialize)]
+struct Env {
+    term: Option<String>,
+    term_program: Option<String>,
+    term_program_version: Option<String>,
+    tmux: bool,
+    screen: bool,
+    ssh: bool,
+}
+
+#[derive(Serialize)]
+struct Quirks {
+    unsupported: bool,
+    string_terminator: &'static str,
+    passthrough: Option<&'static str>,
+    answers_da1: bool,
+}
+
+#[derive(Serialize)]
+struct Query {
+    theme: Option<ThemeMode>,
+    foreground: Option<String>,
+    background: Option<String>,
+    error: Option<QueryError>,
+    timeout_ms: f64,
+    /// Whether the reply to `DA1` arrived before the replies to the color queries,
+    /// which means that the terminal doesn't support them.
+    da1_first: Option<bool>,
+    /// When the reply to each query arrived.
+    timings: Vec<Timing>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exchange: Option<Exchange>,
+}
+
+#[derive(Serialize)]
+struct QueryError {
+    message: String,
+    phase: Option<String>,
+    unsupported_reason: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Timing {
+    reply: &'static str,
+    elapsed_ms: f64,
+}
+
+#[derive(Serialize)]
+struct Exchange {
+    sent: String,
+    received: Vec<Received>,
+}
+
+#[derive(Serialize)]
+struct Received {
+    elapsed_ms: f64,
+    bytes: String,
+}
+
+impl Diagnostics {
+    /// Collects what we know before querying the terminal.
+    pub(crate) fn new(
+        verbosity: Verbosity,
+        quirks: TerminalQuirks,
+        stdout_is_terminal: bool,
+    ) -> Self {
+        let var = |name| env::var(name).ok();
+        let mut diagnostics = Self {
+            env: Env {
+                term: var("TERM"),
+                term_program: var("TERM_PROGRAM"),
+                term_program_version: var("TERM_PROGRAM_VERSION"),
+                tmux: var("TMUX").is_some(),
+                screen: var("STY").is_some(),
+                ssh: var("SSH_TTY").is_some() || var("SSH_CONNECTION").is_some(),
+            },
+            quirks: Quirks {
+                unsupported: quirks.unsupported,
+                string_terminator: match quirks.string_terminator {
+                    StringTerminator::Bel => "BEL",
+                    StringTerminator::St => "ST",
+                },
+                passthrough: quirks.passthrough.map(|p| match p {
+                    Passthrough::Tmux => "tmux",
+                    Passthrough::Screen => "screen",
+                    _ => "other",
+                }),
+                answers_da1: quirks.answers_da1,
+            },
+            stdout_is_terminal,
+            skip_reason: match should_query() {
+                QueryDecision::Skip(reason) => Some(reason.to_string()),
+                _ => None,
+            },
+            query: None,
+            hints: Vec::new(),
+            verbosity,
+        };
+        diagnostics.add_env_hints();
+        diagnostics
+    }
+
+    /// Adds the outcome of querying the terminal.
+    pub(crate) fn record<T: QueryOutcome>(&mut self, result: &Result<T>, recording: &Recording) {
+        let received: Vec<u8> = recording
+            .replies
+            .iter()
+            .flat_map(|r| r.bytes.clone())
+            .collect();
+        let arrival = |needle: &[u8]| {
+            let position = find(&received, needle)?;
+            let mut end = 0;
+            recording.replies.iter().find_map(|reply| {
+                end += reply.bytes.len();
+                (position < end).then_some(reply.elapsed)
+            })
+        };
+        let timings = [
+            ("foreground", &b"\x1b]10;"[..]),
+            ("background", b"\x1b]11;"),
+            ("DA1", b"\x1b[?"),
+        ]
+        .into_iter()
+        .filter_map(|(reply, needle)| {
+            Some(Timing {
+                reply,
+                elapsed_ms: millis(arrival(needle)?),
+            })
+        })
+        .collect();
+        let da1_first = find(&received, b"\x1b[?")
+            .map(|da1| find(&received, b"\x1b]").map_or(true, |osc| da1 < osc));
+        let error = result.as_ref().err().map(|e| QueryError {
+            message: e.to_string(),
+            phase: e.context().phase.map(|p| p.to_string()),
+            unsupported_reason: match e {
+                Error::UnsupportedTerminal(e) => Some(e.reason.to_string()),
+                _ => None,
+            },
+        });
+        let outcome = result.as_ref().ok();
+        let palette = outcome.and_then(QueryOutcome::color_palette);
+        let palette = palette.as_ref();
+        self.query = Some(Query {
+            theme: outcome.map(QueryOutcome::theme_mode),
+            foreground: palette.map(|p| Hex(&p.foreground).to_string()),
+            background: palette.map(|p| Hex(&p.background).to_string()),
+            error,
+            timeout_ms: millis(recording.timeout),
+            da1_first,
+            timings,
+            exchange: (self.verbosity >= Verbosity::Debug).then(|| Exchange {
+                sent: Caret(&recording.query).to_string(),
+                received: recording
+                    .replies
+                    .iter()
+                    .map(|reply| Received {
+                        elapsed_ms: millis(reply.elapsed),
+                        bytes: Caret(&reply.bytes).to_string(),
+                    })
+                    .collect(),
+            }),
+        });
+        if let Err(e) = result {
+            self.add_error_hints(e, recording.timeout);
+        }
+    }
+
+    fn add_env_hints(&mut self) {
+        let term = self.env.term.as_deref().unwrap_or_default();
+        if self.quirks.unsupported {
+            self.hints.push(format!(
+                "TERM={term} is known to not support color queries, so nothing is sent to the terminal"
+            ));
+        }
+        // tmux also uses `TERM=screen` unless configured otherwise.
+        if self.env.screen || (term.starts_with("screen") && !self.env.tmux) {
+            self.hints.push(
+                "GNU Screen relays color queries out of order and is not supported, \
+                 try running termtheme outside of screen"
+                    .to_owned(),
+            );
+        }
+        if self.env.tmux {
+            self.hints.push(
+                "inside tmux, tmux answers the queries instead of the terminal: \
+                 make sure tmux is up to date (3.3 or newer) if the colors look wrong"
+                    .to_owned(),
+            );
+        }
+        if let Some(reason) = &self.skip_reason {
+            self.hints.push(format!(
+                "{reason}: another program (such as a pager) might be using the terminal at the same time"
+            ));
+        }
+        if !self.quirks.answers_da1 {
+            self.hints.push(
+                "the terminal doesn't answer DA1, so unsupported queries only fail after the timeout"
+                    .to_owned(),
+            );
+        }
+    }
+
+    fn add_error_hints(&mut self, error: &Error, timeout: Duration) {
+        match error {
+            Error::Timeout(_) if self.env.ssh => self.hints.push(format!(
+                "the terminal did not reply within {timeout:?}, \
+                 try a longer '--timeout' when connected via SSH"
+            )),
+            Error::Timeout(_) => self
+                .hints
+                .push(format!("the terminal did not reply within {timeout:?}")),
+            Error::UnsupportedTerminal(e) if e.reason == UnsupportedReason::Da1Sentinel => {
+                self.hints.push(
+                    "the terminal answered DA1 but not the color queries, \
+                     which means that it doesn't support them"
+                        .to_owned(),
+                )
+            }
+            _ => {}
+        }
+    }
+
+    /// Prints the diagnostics to stderr.
+    pub(crate) fn print(&self, json: bool) {
+        if json {
+            eprintln!(
+                "{}",
+                serde_json::to_string_pretty(self).expect("serializing never fails")
+            );
+            return;
+        }
+        eprint!("{self}");
+        for hint in &self.hints {
+            display_help(hint);
+        }
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unset = || "(unset)".to_owned();
+        let env = &self.env;
+        writeln!(
+            f,
+            "terminal:   TERM={} TERM_PROGRAM={} TERM_PROGRAM_VERSION={}",
+            env.term.clone().unwrap_or_else(unset),
+            env.term_program.clone().unwrap_or_else(unset),
+            env.term_program_version.clone().unwrap_or_else(unset)
+        )?;
+        let mut environment: Vec<_> =
+            [("tmux", env.tmux), ("screen", env.screen), ("SSH", env.ssh)]
+                .into_iter()
+                .filter_map(|(name, active)| active.then_some(name))
+                .collect();
+        if environment.is_empty() {
+            environment.push("no multiplexer, not connected via SSH");
+        }
+        writeln!(f, "context:    {}", environment.join(", "))?;
+        let quirks = &self.quirks;
+        writeln!(
+            f,
+            "quirks:     {}, terminated by {}, passthrough: {}, {}",
+            if quirks.unsupported {
+                "unsupported"
+            } else {
+                "supported"
+            },
+            quirks.string_terminator,
+            quirks.passthrough.unwrap_or("none"),
+            if quirks.answers_da1 {
+                "answers DA1"
+            } else {
+                "doesn't answer DA1"
+            }
+        )?;
+        writeln!(
+            f,
+            "stdout:     {}",
+            if self.stdout_is_terminal {
+                "terminal"
+            } else {
+                "not a terminal"
+            }
+        )?;
+        writeln!(
+            f,
+            "query:      {}",
+            self.skip_reason
+                .as_deref()
+                .map_or("safe".to_owned(), |r| format!("unsafe, {r}"))
+        )?;
+        let Some(query) = &self.query else {
+            return writeln!(f, "result:     the terminal was not queried");
+        };
+        match (&query.error, query.theme) {
+            (Some(error), _) => writeln!(f, "result:     error: {}", error.message)?,
+            (None, Some(theme)) => writeln!(
+                f,
+                "result:     {} (foreground {}, background {})",
+                DisplayName(theme),
+                query.foreground.as_deref().unwrap_or_default(),
+                query.background.as_deref().unwrap_or_default()
+            )?,
+            (None, None) => {}
+        }
+        if let Some(da1_first) = query.da1_first {
+            writeln!(f, "DA1 first:  {}", if da1_first { "yes" } else { "no" })?;
+        }
+        let mut timings = String::new();
+        for timing in &query.timings {
+            _ = write!(timings, "{} {:.1} ms, ", timing.reply, timing.elapsed_ms);
+        }
+        writeln!(
+            f,
+            "timings:    {}timeout {:.0} ms",
+            timings, query.timeout_ms
+        )?;
+        if let Some(exchange) = &query.exchange {
+            writeln!(f, "sent:       {}", exchange.sent)?;
+            for received in &exchange.received {
+                writeln!(
+                    f,
+                    "received:   {:.1} ms {}",
+                    received.elapsed_ms, received.bytes
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Shows bytes in caret notation (e.g. `^[` for `ESC`),
+/// so that escape sequences don't mess with the terminal.
+struct Caret<'a>(&'a [u8]);
+
+impl fmt::Display for Caret<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            match byte {
+                0x00..=0x1f => write!(f, "^{}", char::from(*byte ^ 0x40))?,
+                0x7f => f.write_str("^?")?,
+                0x20..=0x7e => write!(f, "{}", char::from(*byte))?,
+                _ => write!(f, "\\x{byte:02x}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use terminal_colorsaurus::recording;
+
+    #[test]
+    fn formats_caret_notation() {
+        assert_eq!(
+            r"^[]11;rgb:0000/0000/0000^[\^G^?\xff",
+            Caret(b"\x1b]11;rgb:0000/0000/0000\x1b\\\x07\x7f\xff").to_string()
+        );
+    }
+
+    #[test]
+    fn records_timings_and_da1_order() {
+        let recording = Recording::parse(
+            "query=\\e]10;?\\a\\e]11;?\\a\\e[c\n\
+             reply=0.001000 \\e]10;rgb:ffff/ffff/ffff\\a\n\
+             reply=0.002000 \\e]11;rgb:0000/0000/0000\\a\\e[?62;22c\n",
+        )
+        .unwrap();
+        let result = recording::replay_color_palette(&recording);
+        let mut diagnostics = Diagnostics::new(Verbosity::Debug, recording.quirks, true);
+        diagnostics.record(&result, &recording);
+        let query = diagnostics.query.unwrap();
+        assert_eq!(Some(ThemeMode::Dark), query.theme);
+        assert_eq!(Some(false), query.da1_first);
+        let timings: Vec<_> = query
+            .timings
+            .iter()
+            .map(|t| (t.reply, t.elapsed_ms))
+            .collect();
+        assert_eq!(
+            vec![("foreground", 1.0), ("background", 2.0), ("DA1", 2.0)],
+            timings
+        );
+        assert_eq!(r"^[]10;?^G^[]11;?^G^[[c", query.exchange.unwrap().sent);
+    }
+
+    #[test]
+    fn detects_da1_first() {
+        let recording =
+            Recording::parse("query=\\e]10;?\\a\\e]11;?\\a\\e[c\nreply=0.001000 \\e[?62;22c\n")
+                .unwrap();
+        let result = recording::replay_color_palette(&recording);
+        let mut diagnostics = Diagnostics::new(Verbosity::Verbose, recording.quirks, true);
+        diagnostics.record(&result, &recording);
+        let query = diagnostics.query.unwrap();
+        assert_eq!(Some(true), query.da1_first);
+        assert!(query.exchange.is_none());
+        assert!(query.error.unwrap().unsupported_reason.is_some());
+    }
+}
@@ -0,0 +1,166 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fmt::{self, Write as _};
+use terminal_colorsaurus::{Color, ColorPalette, ThemeMode};
+
+/// How the detected theme is printed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub(crate) enum Format {
+    /// Only the theme mode: `dark` or `light`.
+    #[default]
+    Text,
+    /// A JSON object with the theme mode, the colors and their lightness.
+    Json,
+    /// `export` statements to `eval` in a shell.
+    Shell,
+    /// `KEY=value` lines as understood by `env` and `.env` files.
+    Env,
+}
+
+impl Format {
+    /// Formats the theme mode and the palette it was derived from.
+    /// The palette is missing if the theme mode is a fallback.
+    pub(crate) fn format(self, theme: ThemeMode, palette: Option<&ColorPalette>) -> String {
+        match self {
+            Format::Text => DisplayName(theme).to_string(),
+            Format::Json => serde_json::to_string(&JsonOutput::new(theme, palette))
+                .expect("serializing never fails"),
+            Format::Shell => format_variables(theme, palette, |name, value| {
+                format!("export {name}='{value}'")
+            }),
+            Format::Env => {
+                format_variables(theme, palette, |name, value| format!("{name}={value}"))
+            }
+        }
+    }
+
+    pub(crate) fn format_palette(self, palette: &ColorPalette) -> String {
+        self.format(palette.theme_mode(), Some(palette))
+    }
+}
+
+#[derive(Serialize)]
+struct JsonOutput {
+    theme: ThemeMode,
+    foreground: Option<String>,
+    background: Option<String>,
+    lightness: Option<Lightness>,
+}
+
+#[derive(Serialize)]
+struct Lightness {
+    foreground: f32,
+    background: f32,
+}
+
+impl JsonOutput {
+    fn new(theme: ThemeMode, palette: Option<&ColorPalette>) -> Self {
+        Self {
+            theme,
+            foreground: palette.map(|p| Hex(&p.foreground).to_string()),
+            background: palette.map(|p| Hex(&p.background).to_string()),
+            lightness: palette.map(|p| Lightness {
+                foreground: p.foreground.perceived_lightness(),
+                background: p.background.perceived_lightness(),
+            }),
+        }
+    }
+}
+
+// The shell output quotes the values even though they never need quoting
+// to keep it safe to `eval` should that ever change.
+// `env` and `.env` files take values literally, so the quotes would end up in the value.
+fn format_variables(
+    theme: ThemeMode,
+    palette: Option<&ColorPalette>,
+    format_variable: impl Fn(&str, &str) -> String,
+) -> String {
+    let mut output = String::new();
+    for (name, value) in variables(theme, palette) {
+        _ = writeln!(output, "{}", format_variable(name, &value));
+    }
+    output.pop(); // The caller decides about the trailing newline.
+    output
+}
+
+/// The environment variables describing the theme:
+/// `TERM_THEME` and, if known, `TERM_FOREGROUND` and `TERM_BACKGROUND`.
+pub(crate) fn variables(
+    theme: ThemeMode,
+    palette: Option<&ColorPalette>,
+) -> Vec<(&'static str, String)> {
+    let mut variables = vec![("TERM_THEME", DisplayName(theme).to_string())];
+    if let Some(palette) = palette {
+        variables.push(("TERM_FOREGROUND", Hex(&palette.foreground).to_string()));
+        variables.push(("TERM_BACKGROUND", Hex(&palette.background).to_string()));
+    }
+    variables
+}
+
+/// Formats a color as `#rrggbb`.
+pub(crate) struct Hex<'a>(pub(crate) &'a Color);
+
+impl fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (r, g, b) = self.0.scale_to_8bit();
+        write!(f, "#{r:02x}{g:02x}{b:02x}")
+    }
+}
+
+pub(crate) struct DisplayName(pub(crate) ThemeMode);
+
+impl fmt::Display for DisplayName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            ThemeMode::Dark => f.write_str("dark"),
+            ThemeMode::Light => f.write_str("light"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `ColorPalette` can't be constructed outside of terminal-colorsaurus.
+    fn palette() -> ColorPalette {
+        serde_json::from_str(r##"{"foreground":"#ffffff","background":"#1e1e2e"}"##)
+            .expect("valid palette")
+    }
+
+    #[test]
+    fn formats_json() {
+        let json = Format::Json.format_palette(&palette());
+        assert!(json.starts_with(
+            r##"{"theme":"dark","foreground":"#ffffff","background":"#1e1e2e","lightness":{"foreground":1.0,"##
+        ), "{json}");
+    }
+
+    #[test]
+    fn formats_fallback_without_colors() {
+        assert_eq!(
+            r#"{"theme":"light","foreground":null,"background":null,"lightness":null}"#,
+            Format::Json.format(ThemeMode::Light, None)
+        );
+        assert_eq!(
+            "TERM_THEME=light",
+            Format::Env.format(ThemeMode::Light, None)
+        );
+    }
+
+    #[test]
+    fn formats_shell_exports() {
+        assert_eq!(
+            "export TERM_THEME='dark'\nexport TERM_FOREGROUND='#ffffff'\nexport TERM_BACKGROUND='#1e1e2e'",
+            Format::Shell.format_palette(&palette())
+        );
+    }
+
+    #[test]
+    fn formats_env_without_quotes() {
+        assert_eq!(
+            "TERM_THEME=dark\nTERM_FOREGROUND=#ffffff\nTERM_BACKGROUND=#1e1e2e",
+            Format::Env.format_palette(&palette())
+        );
+    }
+}
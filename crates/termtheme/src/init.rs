@@ -0,0 +1,220 @@
+use crate::format::DisplayName;
+use crate::{CliError, Fallback};
+use clap::ValueEnum;
+use std::fmt::Write as _;
+use terminal_colorsaurus::ThemeMode;
+
+#[derive(clap::Args, Debug)]
+pub(crate) struct InitArgs {
+    /// The shell to print the snippet for.
+    shell: Shell,
+    /// Export a variable when the theme is dark, e.g. 'BAT_THEME=Catppuccin Mocha'.
+    /// Can be repeated. Each variable needs a value for both themes.
+    #[arg(long, value_name = "NAME=VALUE", value_parser = parse_variable)]
+    dark: Vec<(String, String)>,
+    /// Export a variable when the theme is light, e.g. 'BAT_THEME=Catppuccin Latte'.
+    #[arg(long, value_name = "NAME=VALUE", value_parser = parse_variable)]
+    light: Vec<(String, String)>,
+    /// The theme to assume if it can't be detected.
+    #[arg(long, value_enum, default_value = "dark")]
+    fallback: Fallback,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Nushell,
+}
+
+/// A variable with its value for the dark and the light theme.
+type Variable = (String, String, String);
+
+/// Prints a snippet that detects the theme when an interactive shell starts
+/// and exports the variables for it.
+pub(crate) fn init(args: &InitArgs) -> Result<(), CliError> {
+    let variables = variables(&args.dark, &args.light).map_err(CliError::Other)?;
+    let fallback = DisplayName(ThemeMode::from(args.fallback));
+    // The cache spares new shells in the same terminal the round trip.
+    // stdout is captured by the shell, so we need '--force'.
+    let command = format!("termtheme --force --cache --fallback {fallback}");
+    println!("{}", snippet(args.shell, &command, &variables));
+    Ok(())
+}
+
+fn parse_variable(input: &str) -> Result<(String, String), String> {
+    let (name, value) = input
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=VALUE, got '{input}'"))?;
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(format!("invalid variable name '{name}'"));
+    }
+    Ok((name.to_owned(), value.to_owned()))
+}
+
+/// The default variables, overridden and extended by the user's mapping.
+fn variables(
+    dark: &[(String, String)],
+    light: &[(String, String)],
+) -> Result<Vec<Variable>, String> {
+    let mut variables: Vec<Variable> = [
+        ("TERM_THEME", "dark", "light"),
+        // Light text on a dark background and vice versa.
+        ("COLORFGBG", "15;0", "0;15"),
+        // bat's default themes.
+        ("BAT_THEME", "Monokai Extended", "Monokai Extended Light"),
+    ]
+    .into_iter()
+    .map(|(name, dark, light)| (name.to_owned(), dark.to_owned(), light.to_owned()))
+    .collect();
+    let lookup = |values: &[(String, String)], name: &str| {
+        values
+            .iter()
+            .rev()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.clone())
+    };
+    for (name, _) in dark.iter().chain(light) {
+        if let Some(existing) = variables.iter_mut().find(|(n, _, _)| n == name) {
+            existing.1 = lookup(dark, name).unwrap_or_else(|| existing.1.clone());
+            existing.2 = lookup(light, name).unwrap_or_else(|| existing.2.clone());
+            continue;
+        }
+        match (lookup(dark, name), lookup(light, name)) {
+            (Some(dark), Some(light)) => variables.push((name.clone(), dark, light)),
+            (None, _) => return Err(format!("{name} is missing a value for the dark theme")),
+            (_, None) => return Err(format!("{name} is missing a value for the light theme")),
+        }
+    }
+    Ok(variables)
+}
+
+fn snippet(shell: Shell, command: &str, variables: &[Variable]) -> String {
+    let exports = |light: bool, indent: &str| {
+        let mut exports = String::new();
+        for (name, dark, light_value) in variables {
+            let value = if light { light_value } else { dark };
+            _ = match shell {
+                Shell::Bash | Shell::Zsh => {
+                    writeln!(exports, "{indent}export {name}={}", posix_quote(value))
+                }
+                Shell::Fish => writeln!(exports, "{indent}set -gx {name} {}", fish_quote(value)),
+                Shell::Nushell => writeln!(exports, "{indent}{name}: {}", nu_quote(value)),
+            };
+        }
+        exports
+    };
+    let (light, dark) = (exports(true, "        "), exports(false, "        "));
+    match shell {
+        Shell::Bash | Shell::Zsh => format!(
+            "if [[ $- == *i* ]]; then\n    \
+                 if [[ \"$(command {command} 2>/dev/null)\" == light ]]; then\n\
+                 {light}    else\n\
+                 {dark}    fi\n\
+             fi"
+        ),
+        Shell::Fish => format!(
+            "if status is-interactive\n    \
+                 set -l __termtheme (command {command} 2>/dev/null)\n    \
+                 if test \"$__termtheme\" = light\n\
+                 {light}    else\n\
+                 {dark}    end\n\
+             end"
+        ),
+        Shell::Nushell => format!(
+            "load-env (\n    \
+                 if not $nu.is-interactive {{\n        {{}}\n    \
+                 }} else if (^{command} | complete | get stdout | str trim) == \"light\" {{ {{\n\
+                 {light}    }} }} else {{ {{\n\
+                 {dark}    }} }}\n\
+             )"
+        ),
+    }
+}
+
+fn posix_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+fn fish_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', r"\\").replace('\'', r"\'"))
+}
+
+// Nushell's double-quoted strings use the same escapes as JSON.
+fn nu_quote(value: &str) -> String {
+    serde_json::to_string(value).expect("serializing never fails")
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn variable(name: &str, value: &str) -> (String, String) {
+        (name.to_owned(), value.to_owned())
+    }
+
+    #[test]
+    fn overrides_and_extends_defaults() {
+        let variables = variables(
+            &[variable("BAT_THEME", "Nord"), variable("FZF_THEME", "dark")],
+            &[variable("FZF_THEME", "light")],
+        )
+        .unwrap();
+        assert_eq!(4, variables.len());
+        assert_eq!("Nord", variables[2].1);
+        assert_eq!("Monokai Extended Light", variables[2].2);
+        assert_eq!(
+            (
+                "FZF_THEME".to_owned(),
+                "dark".to_owned(),
+                "light".to_owned()
+            ),
+            variables[3]
+        );
+    }
+
+    #[test]
+    fn rejects_variables_without_both_values() {
+        assert!(variables(&[variable("FZF_THEME", "dark")], &[]).is_err());
+        assert!(variables(&[], &[variable("FZF_THEME", "light")]).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_names() {
+        assert!(parse_variable("1X=y").is_err());
+        assert!(parse_variable("A-B=y").is_err());
+        assert!(parse_variable("A_B").is_err());
+        assert_eq!(Ok(variable("A_B", "x=y")), parse_variable("A_B=x=y"));
+    }
+
+    #[test]
+    fn quotes_values() {
+        assert_eq!(r"'it'\''s'", posix_quote("it's"));
+        assert_eq!(r"'it\'s \\'", fish_quote(r"it's \"));
+        assert_eq!(r#""say \"hi\"""#, nu_quote(r#"say "hi""#));
+    }
+
+    #[test]
+    fn prints_bash_snippet() {
+        let variables = vec![(
+            "TERM_THEME".to_owned(),
+            "dark".to_owned(),
+            "light".to_owned(),
+        )];
+        assert_eq!(
+            "\
+if [[ $- == *i* ]]; then
+    if [[ \"$(command termtheme 2>/dev/null)\" == light ]]; then
+        export TERM_THEME='light'
+    else
+        export TERM_THEME='dark'
+    fi
+fi",
+            snippet(Shell::Bash, "termtheme", &variables)
+        );
+    }
+}
@@ -1,35 +1,197 @@
 use anstyle::{AnsiColor, Style};
-use clap::Parser;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use diagnostics::{Diagnostics, DiagnosticsArgs};
+use format::Format;
 use std::{
+    ffi::OsString,
     fmt::{self, Display},
     io::{self, stdout, IsTerminal},
     process::exit,
+    time::Duration,
 };
-use terminal_colorsaurus::{theme_mode, QueryOptions, ThemeMode};
+use terminal_colorsaurus::cache::{self, CacheOptions};
+use terminal_colorsaurus::quirks::terminal_quirks;
+use terminal_colorsaurus::recording::record_color_palette;
+use terminal_colorsaurus::{color_palette, Error, Phase, QueryOptions, ThemeMode};
+
+mod diagnostics;
+mod format;
+mod init;
+mod palette;
+mod set;
+mod theme_file;
+mod watch;
 
 fn main() {
-    let args = Args::parse();
-    if !stdout().is_terminal() && !args.force {
-        display_error("stdout is not connected to a terminal");
-        display_help(
-            "use '--force' if you're sure that no other process is trying to write to the terminal",
-        );
-        exit(1);
-    }
-    match theme_mode(QueryOptions::default()) {
-        Ok(s) => display_theme_mode(s, !args.no_newline),
-        Err(e) => {
-            display_error(e);
-            exit(1);
+    let args = try_parse_args(std::env::args_os()).unwrap_or_else(|e| e.exit());
+    let mut options = QueryOptions::default();
+    options.timeout = args.timeout;
+    let result = match &args.command {
+        Some(Command::Watch(watch)) => watch::watch(watch, options, &args.diagnostics),
+        Some(Command::Palette(palette)) => palette::palette(palette, options, &args.diagnostics),
+        Some(Command::Set(set)) => set::set(set, options, &args.diagnostics),
+        Some(Command::Reset) => set::reset(),
+        Some(Command::Apply(apply)) => set::apply(apply, options, &args.diagnostics),
+        Some(Command::Init(init)) => init::init(init),
+        None => detect(&args.detect, &args.diagnostics, args.format, options),
+    };
+    if let Err(e) = result {
+        display_error(&e);
+        if let CliError::NotATerminal = e {
+            display_help(
+                "use '--force' if you're sure that no other process is trying to write to the terminal",
+            );
+        }
+        exit(exit_code(&e));
+    }
+}
+
+// The detection flags live on the top-level command so that `termtheme -n` works,
+// but they are meaningless for subcommands. Clap can't reject them for subcommands only
+// (`args_conflicts_with_subcommands` also rejects the global flags), so we do it by hand.
+// The same goes for the diagnostics with subcommands that don't query the terminal
+// and for the top-level '--format' (the subcommands that print something have their own).
+fn try_parse_args(
+    args: impl IntoIterator<Item = impl Into<OsString> + Clone>,
+) -> Result<Args, clap::Error> {
+    let mut command = Args::command();
+    let matches = command.try_get_matches_from_mut(args)?;
+    let args = Args::from_arg_matches(&matches)?;
+    let conflicting_flag = match &args.command {
+        None => None,
+        Some(Command::Reset | Command::Init(_)) => args
+            .detect
+            .used_flag()
+            .or_else(|| args.diagnostics.used_flag()),
+        Some(_) => args.detect.used_flag(),
+    }
+    .or_else(|| {
+        let format_used = matches.value_source("format") == Some(ValueSource::CommandLine);
+        (args.command.is_some() && format_used).then_some("--format")
+    });
+    match (matches.subcommand_name(), conflicting_flag) {
+        (Some(subcommand), Some(flag)) => Err(command.error(
+            ErrorKind::ArgumentConflict,
+            format!("the argument '{flag}' cannot be used with 'termtheme {subcommand}'"),
+        )),
+        _ => Ok(args),
+    }
+}
+
+fn detect(
+    args: &DetectArgs,
+    diagnostics_args: &DiagnosticsArgs,
+    format: Format,
+    options: QueryOptions,
+) -> Result<(), CliError> {
+    let stdout_is_terminal = stdout().is_terminal();
+    let mut diagnostics = diagnostics_args
+        .verbosity()
+        .map(|v| Diagnostics::new(v, terminal_quirks(), stdout_is_terminal));
+    if !stdout_is_terminal && !args.force {
+        if let Some(diagnostics) = &diagnostics {
+            diagnostics.print(diagnostics_args.json);
+        }
+        if let Some(fallback) = args.fallback {
+            display_fallback(args, format, fallback);
+            return Ok(());
+        }
+        return Err(CliError::NotATerminal);
+    }
+    let palette = if let Some(diagnostics) = &mut diagnostics {
+        // Diagnostics are about the terminal, so the cache is bypassed.
+        let (palette, recording) = record_color_palette(options);
+        diagnostics.record(&palette, &recording);
+        diagnostics.print(diagnostics_args.json);
+        palette
+    } else if args.cache {
+        cache::color_palette(options, &CacheOptions::default())
+    } else {
+        color_palette(options)
+    };
+    match (palette, args.fallback) {
+        (Ok(palette), _) => display_output(&format.format_palette(&palette), !args.no_newline),
+        (Err(_), Some(fallback)) => display_fallback(args, format, fallback),
+        (Err(e), None) => return Err(e.into()),
+    }
+    Ok(())
+}
+
+fn display_fallback(args: &DetectArgs, format: Format, fallback: Fallback) {
+    let output = format.format(ThemeMode::from(fallback), None);
+    display_output(&output, !args.no_newline)
+}
+
+const EXIT_ERROR: i32 = 1;
+// 2 is used by clap for usage errors.
+const EXIT_NOT_A_TERMINAL: i32 = 3;
+const EXIT_UNSUPPORTED: i32 = 4;
+const EXIT_TIMEOUT: i32 = 5;
+const EXIT_INVALID_RESPONSE: i32 = 6;
+
+const EXIT_CODES_HELP: &str = "\
+Exit Codes:
+  0  The theme was detected (or the fallback was used)
+  1  An I/O error occurred
+  2  Invalid arguments
+  3  There is no terminal or stdout is not connected to it (see '--force')
+  4  The terminal does not support querying for its colors
+  5  The terminal did not reply in time (see '--timeout')
+  6  The terminal's reply could not be parsed";
+
+/// An error that ends the program. [`main`] prints it and exits with its [`exit_code`].
+#[derive(Debug)]
+enum CliError {
+    /// Querying or writing to the terminal failed.
+    Terminal(Error),
+    /// stdout is not connected to a terminal and '--force' wasn't given.
+    NotATerminal,
+    /// Any other error, e.g. a theme file that can't be read.
+    Other(String),
+}
+
+impl From<Error> for CliError {
+    fn from(value: Error) -> Self {
+        CliError::Terminal(value)
+    }
+}
+
+impl From<io::Error> for CliError {
+    fn from(value: io::Error) -> Self {
+        CliError::Terminal(value.into())
+    }
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Terminal(e) => e.fmt(f),
+            CliError::NotATerminal => f.write_str("stdout is not connected to a terminal"),
+            CliError::Other(message) => f.write_str(message),
+        }
+    }
+}
+
+fn exit_code(error: &CliError) -> i32 {
+    match error {
+        CliError::Terminal(Error::UnsupportedTerminal(_)) => EXIT_UNSUPPORTED,
+        CliError::Terminal(Error::Timeout(_)) => EXIT_TIMEOUT,
+        CliError::Terminal(Error::Parse(_) | Error::InvalidResponse(_)) => EXIT_INVALID_RESPONSE,
+        CliError::Terminal(e @ Error::Io(_)) if e.context().phase == Some(Phase::OpenTerminal) => {
+            EXIT_NOT_A_TERMINAL
         }
+        CliError::NotATerminal => EXIT_NOT_A_TERMINAL,
+        _ => EXIT_ERROR,
     }
 }
 
-fn display_theme_mode(theme_mode: ThemeMode, newline: bool) {
+fn display_output(output: &str, newline: bool) {
     if newline {
-        println!("{}", DisplayName(theme_mode))
+        println!("{output}")
     } else {
-        print!("{}", DisplayName(theme_mode))
+        print!("{output}")
     }
 }
 
@@ -53,26 +215,122 @@ fn display_help(e: impl Display) {
     }
 }
 
-struct DisplayName(ThemeMode);
-
-impl fmt::Display for DisplayName {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self.0 {
-            ThemeMode::Dark => f.write_str("dark"),
-            ThemeMode::Light => f.write_str("light"),
-        }
-    }
-}
-
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, after_help = EXIT_CODES_HELP)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    detect: DetectArgs,
+    #[command(flatten)]
+    diagnostics: DiagnosticsArgs,
+    /// The output format.
+    #[arg(long, value_enum, default_value_t)]
+    format: Format,
+    /// The maximum time to wait for the terminal's reply, e.g. '500ms' or '2s'.
+    #[arg(long, value_parser = parse_duration, default_value = "1s", global = true)]
+    timeout: Duration,
+}
+
+/// The flags for detecting the theme when no subcommand is given.
+#[derive(clap::Args, Debug, Default, PartialEq, Eq)]
+struct DetectArgs {
     /// Do not output a newline.
     #[arg(short = 'n')]
     no_newline: bool,
     /// Always query the terminal even when stdout is redirected.
     #[arg(short = 'f', long)]
     force: bool,
+    /// Reuse the colors detected earlier in this terminal.
+    ///
+    /// 'termtheme set', 'apply' and 'reset' invalidate the cache,
+    /// as does 'termtheme watch' when the theme changes.
+    #[arg(long)]
+    cache: bool,
+    /// The theme to print if it can't be detected. Always exits successfully.
+    #[arg(long, value_enum)]
+    fallback: Option<Fallback>,
+}
+
+impl DetectArgs {
+    /// The first flag that was passed, if any.
+    fn used_flag(&self) -> Option<&'static str> {
+        let Self {
+            no_newline,
+            force,
+            cache,
+            fallback,
+        } = self;
+        [
+            (*no_newline, "-n"),
+            (*force, "--force"),
+            (*cache, "--cache"),
+            (fallback.is_some(), "--fallback"),
+        ]
+        .into_iter()
+        .find_map(|(used, flag)| used.then_some(flag))
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the theme and then again whenever it changes. Stop with Ctrl+C.
+    ///
+    /// Terminals that support color scheme notifications (DEC mode 2031)
+    /// report changes as they happen. Other terminals are queried periodically.
+    /// The terminal is queried even if stdout is redirected.
+    Watch(watch::WatchArgs),
+    /// Print the terminal's colors with their lightness and contrast against the background.
+    ///
+    /// Includes the cursor and selection colors and the 16 ANSI colors if the terminal reports them.
+    /// The terminal is queried even if stdout is redirected.
+    Palette(palette::PaletteArgs),
+    /// Set the terminal's colors and read them back to confirm that they were applied.
+    Set(set::SetArgs),
+    /// Reset the terminal's colors to its defaults.
+    Reset,
+    /// Set the terminal's colors from a theme file.
+    Apply(set::ApplyArgs),
+    /// Print a snippet for your shell's config that exports variables such as
+    /// TERM_THEME, COLORFGBG and BAT_THEME according to the theme.
+    ///
+    /// The theme is detected once when an interactive shell starts. The result is cached
+    /// (see '--cache'), so that new shells in the same terminal start without delay.
+    ///
+    /// bash: eval "$(termtheme init bash)"
+    /// zsh:  eval "$(termtheme init zsh)"
+    /// fish: termtheme init fish | source
+    /// nushell: termtheme init nushell | save -f ~/.cache/termtheme.nu (then 'source' it)
+    #[command(verbatim_doc_comment)]
+    Init(init::InitArgs),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Fallback {
+    Dark,
+    Light,
+}
+
+impl From<Fallback> for ThemeMode {
+    fn from(value: Fallback) -> Self {
+        match value {
+            Fallback::Dark => ThemeMode::Dark,
+            Fallback::Light => ThemeMode::Light,
+        }
+    }
+}
+
+fn parse_duration(input: &str) -> Result<Duration, String> {
+    let (number, unit) = if let Some(millis) = input.strip_suffix("ms") {
+        (millis, 1e-3)
+    } else {
+        (input.strip_suffix('s').unwrap_or(input), 1.0)
+    };
+    number
+        .parse::<f64>()
+        .ok()
+        .and_then(|n| Duration::try_from_secs_f64(n * unit).ok())
+        .ok_or_else(|| format!("invalid duration '{input}', expected e.g. '500ms' or '2s'"))
 }
 
 trait Stream: io::Write + io::IsTerminal {}
@@ -96,3 +354,62 @@ fn use_colors(raw: &dyn Stream) -> bool {
             && (anstyle_query::term_supports_color() || clicolor_enabled || anstyle_query::is_ci())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(Ok(Duration::from_millis(500)), parse_duration("500ms"));
+        assert_eq!(Ok(Duration::from_millis(1500)), parse_duration("1.5s"));
+        assert_eq!(Ok(Duration::from_secs(2)), parse_duration("2"));
+        assert!(parse_duration("-1s").is_err());
+        assert!(parse_duration("1m").is_err());
+    }
+
+    #[test]
+    fn accepts_global_flags_before_subcommands() {
+        let args = try_parse_args(["termtheme", "--timeout", "2s", "palette"]).expect("valid");
+        assert_eq!(Duration::from_secs(2), args.timeout);
+        assert!(matches!(args.command, Some(Command::Palette(_))));
+        assert!(try_parse_args(["termtheme", "palette", "--format", "json"]).is_ok());
+        assert!(try_parse_args(["termtheme", "watch", "--format", "shell"]).is_ok());
+        assert!(try_parse_args(["termtheme", "--verbose", "palette"]).is_ok());
+        assert!(try_parse_args(["termtheme", "apply", "theme.yaml", "--json"]).is_ok());
+    }
+
+    #[test]
+    fn rejects_detection_flags_with_subcommands() {
+        let error = try_parse_args(["termtheme", "-n", "palette"]).expect_err("-n is rejected");
+        assert_eq!(ErrorKind::ArgumentConflict, error.kind());
+        assert!(error
+            .to_string()
+            .contains("'-n' cannot be used with 'termtheme palette'"));
+        assert!(try_parse_args(["termtheme", "--fallback", "dark", "watch"]).is_err());
+        assert!(try_parse_args(["termtheme", "palette", "-n"]).is_err());
+        assert!(try_parse_args(["termtheme", "-n", "--cache"]).is_ok());
+        assert!(try_parse_args(["termtheme", "reset", "--debug"]).is_err());
+        assert!(try_parse_args(["termtheme", "--format", "json", "palette"]).is_err());
+    }
+
+    #[test]
+    fn rejects_variable_formats_for_palette() {
+        for format in ["shell", "env"] {
+            let error = try_parse_args(["termtheme", "palette", "--format", format])
+                .expect_err("only text and json are accepted");
+            assert_eq!(ErrorKind::InvalidValue, error.kind());
+        }
+    }
+
+    #[test]
+    fn reports_missing_terminal_as_not_a_terminal() {
+        let mut error = CliError::from(io::Error::from(io::ErrorKind::NotFound));
+        assert_eq!(EXIT_ERROR, exit_code(&error));
+        if let CliError::Terminal(Error::Io(e)) = &mut error {
+            e.context.phase = Some(Phase::OpenTerminal);
+        }
+        assert_eq!(EXIT_NOT_A_TERMINAL, exit_code(&error));
+        assert_eq!(EXIT_NOT_A_TERMINAL, exit_code(&CliError::NotATerminal));
+    }
+}
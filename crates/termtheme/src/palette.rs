@@ -0,0 +1,182 @@
+use crate::diagnostics::DiagnosticsArgs;
+use crate::format::{DisplayName, Hex};
+use crate::{use_colors, CliError};
+use anstyle::{RgbColor, Style};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::io;
+use terminal_colorsaurus::recording::record_extended_color_palette;
+use terminal_colorsaurus::{
+    extended_color_palette, Color, ExtendedColorPalette, QueryOptions, ThemeMode,
+};
+
+#[derive(clap::Args, Debug)]
+pub(crate) struct PaletteArgs {
+    /// Include the 240 colors of the 256-color palette that follow the 16 ANSI colors.
+    #[arg(long)]
+    all: bool,
+    /// The output format.
+    #[arg(long, value_enum, default_value_t)]
+    format: PaletteFormat,
+}
+
+/// How the palette is printed.
+/// Unlike the theme, the palette doesn't map to environment variables.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum PaletteFormat {
+    /// A table with the colors, their lightness and their contrast against the background.
+    #[default]
+    Text,
+    /// A JSON object with the same information as the table.
+    Json,
+}
+
+const ANSI_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// Queries the terminal's full palette and prints it as a table or as JSON.
+pub(crate) fn palette(
+    args: &PaletteArgs,
+    options: QueryOptions,
+    diagnostics: &DiagnosticsArgs,
+) -> Result<(), CliError> {
+    let palette = diagnostics.query(
+        options,
+        extended_color_palette,
+        record_extended_color_palette,
+    )?;
+    let ansi = if args.all {
+        &palette.ansi[..]
+    } else {
+        &palette.ansi[..16]
+    };
+    if args.format == PaletteFormat::Json {
+        let output = JsonOutput::new(&palette, ansi);
+        println!(
+            "{}",
+            serde_json::to_string(&output).expect("serializing never fails")
+        );
+    } else {
+        println!("{}", format_table(&palette, ansi));
+    }
+    Ok(())
+}
+
+fn named_colors(palette: &ExtendedColorPalette) -> [(&'static str, Option<&Color>); 6] {
+    [
+        ("foreground", Some(&palette.foreground)),
+        ("background", Some(&palette.background)),
+        ("cursor", palette.cursor.as_ref()),
+        ("cursor text", palette.cursor_text.as_ref()),
+        ("selection fg", palette.selection_foreground.as_ref()),
+        ("selection bg", palette.selection_background.as_ref()),
+    ]
+}
+
+fn ansi_name(index: usize) -> String {
+    match index {
+        0..=7 => format!("{index} {}", ANSI_NAMES[index]),
+        8..=15 => format!("{index} bright {}", ANSI_NAMES[index - 8]),
+        _ => index.to_string(),
+    }
+}
+
+fn format_table(palette: &ExtendedColorPalette, ansi: &[Option<Color>]) -> String {
+    let colors = use_colors(&io::stdout());
+    let rows = named_colors(palette)
+        .into_iter()
+        .map(|(name, color)| (name.to_owned(), color))
+        .chain(
+            ansi.iter()
+                .enumerate()
+                .map(|(index, color)| (ansi_name(index), color.as_ref())),
+        );
+    let mut output = format!(
+        "theme: {}\n\n{:<18} {:<10} {:>9} {:>8}",
+        DisplayName(palette.theme_mode()),
+        "COLOR",
+        "HEX",
+        "LIGHTNESS",
+        "CONTRAST"
+    );
+    for (name, color) in rows {
+        let Some(color) = color else {
+            _ = write!(
+                output,
+                "\n{name:<18} {:<10} {:>9} {:>8}",
+                "unknown", "-", "-"
+            );
+            continue;
+        };
+        let hex = Hex(color).to_string();
+        // The swatch and the hex value together are as wide as the column.
+        let swatch = if colors {
+            let style = Style::new().bg_color(Some(RgbColor::from(color.clone()).into()));
+            format!("{style}  {style:#} {hex}")
+        } else {
+            format!("{hex:<10}")
+        };
+        _ = write!(
+            output,
+            "\n{name:<18} {swatch} {:>9.2} {:>8.2}",
+            color.perceived_lightness(),
+            color.contrast_ratio(&palette.background)
+        );
+    }
+    output
+}
+
+#[derive(Serialize)]
+struct JsonOutput {
+    theme: ThemeMode,
+    foreground: Swatch,
+    background: Swatch,
+    cursor: Option<Swatch>,
+    cursor_text: Option<Swatch>,
+    selection_foreground: Option<Swatch>,
+    selection_background: Option<Swatch>,
+    ansi: Vec<Option<Swatch>>,
+}
+
+#[derive(Serialize)]
+struct Swatch {
+    color: String,
+    lightness: f32,
+    /// The contrast ratio against the background.
+    contrast: f32,
+}
+
+impl JsonOutput {
+    fn new(palette: &ExtendedColorPalette, ansi: &[Option<Color>]) -> Self {
+        let swatch = |color: &Color| Swatch {
+            color: Hex(color).to_string(),
+            lightness: color.perceived_lightness(),
+            contrast: color.contrast_ratio(&palette.background),
+        };
+        Self {
+            theme: palette.theme_mode(),
+            foreground: swatch(&palette.foreground),
+            background: swatch(&palette.background),
+            cursor: palette.cursor.as_ref().map(swatch),
+            cursor_text: palette.cursor_text.as_ref().map(swatch),
+            selection_foreground: palette.selection_foreground.as_ref().map(swatch),
+            selection_background: palette.selection_background.as_ref().map(swatch),
+            ansi: ansi.iter().map(|c| c.as_ref().map(swatch)).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_ansi_colors() {
+        assert_eq!("0 black", ansi_name(0));
+        assert_eq!("9 bright red", ansi_name(9));
+        assert_eq!("15 bright white", ansi_name(15));
+        assert_eq!("16", ansi_name(16));
+    }
+}
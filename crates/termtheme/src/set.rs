@@ -0,0 +1,188 @@
+use crate::diagnostics::DiagnosticsArgs;
+use crate::format::Hex;
+use crate::theme_file::{parse_color, parse_theme, Theme};
+use crate::CliError;
+use std::fs;
+use std::io::{self, Write as _};
+use std::path::PathBuf;
+use terminal_colorsaurus::cache::{self, CacheOptions};
+use terminal_colorsaurus::quirks::terminal_quirks;
+use terminal_colorsaurus::recording::record_extended_color_palette;
+use terminal_colorsaurus::{extended_color_palette, Color, QueryOptions};
+
+#[derive(clap::Args, Debug)]
+#[group(required = true, multiple = true)]
+pub(crate) struct SetArgs {
+    /// The foreground color, e.g. '#cdd6f4' or 'rgb:cd/d6/f4'.
+    #[arg(long, value_parser = parse_color)]
+    fg: Option<Color>,
+    /// The background color.
+    #[arg(long, value_parser = parse_color)]
+    bg: Option<Color>,
+    /// The cursor color.
+    #[arg(long, value_parser = parse_color)]
+    cursor: Option<Color>,
+    /// An indexed color, e.g. '1=#f38ba8' for red. Can be repeated.
+    #[arg(long = "color", value_name = "INDEX=COLOR", value_parser = parse_indexed_color)]
+    colors: Vec<(u8, Color)>,
+}
+
+#[derive(clap::Args, Debug)]
+pub(crate) struct ApplyArgs {
+    /// A base16 scheme (YAML) or the JSON printed by 'termtheme --format json'
+    /// or 'termtheme palette --format json'.
+    theme_file: PathBuf,
+}
+
+fn parse_indexed_color(input: &str) -> Result<(u8, Color), String> {
+    let (index, color) = input
+        .split_once('=')
+        .ok_or_else(|| format!("expected INDEX=COLOR, got '{input}'"))?;
+    let index = index
+        .parse()
+        .map_err(|_| format!("invalid color index '{index}', expected 0 to 255"))?;
+    Ok((index, parse_color(color)?))
+}
+
+pub(crate) fn set(
+    args: &SetArgs,
+    options: QueryOptions,
+    diagnostics: &DiagnosticsArgs,
+) -> Result<(), CliError> {
+    let theme = Theme {
+        foreground: args.fg.clone(),
+        background: args.bg.clone(),
+        cursor: args.cursor.clone(),
+        ansi: args.colors.clone(),
+    };
+    apply_theme(&theme, options, diagnostics)
+}
+
+pub(crate) fn apply(
+    args: &ApplyArgs,
+    options: QueryOptions,
+    diagnostics: &DiagnosticsArgs,
+) -> Result<(), CliError> {
+    let path = args.theme_file.display();
+    let input = fs::read_to_string(&args.theme_file)
+        .map_err(|e| CliError::Other(format!("failed to read {path}: {e}")))?;
+    let theme =
+        parse_theme(&input).map_err(|e| CliError::Other(format!("failed to parse {path}: {e}")))?;
+    apply_theme(&theme, options, diagnostics)
+}
+
+/// Resets the foreground, background, cursor and indexed colors
+/// to the terminal's defaults (`OSC 110`, `OSC 111`, `OSC 112` and `OSC 104`).
+pub(crate) fn reset() -> Result<(), CliError> {
+    write_sequences(
+        [b"\x1b]110", b"\x1b]111", b"\x1b]112", b"\x1b]104"]
+            .into_iter()
+            .map(|s| s.to_vec()),
+    )?;
+    Ok(())
+}
+
+/// Sets the colors and reads them back to make sure that the terminal applied them.
+fn apply_theme(
+    theme: &Theme,
+    options: QueryOptions,
+    diagnostics: &DiagnosticsArgs,
+) -> Result<(), CliError> {
+    let named = [
+        ("foreground", 10, &theme.foreground),
+        ("background", 11, &theme.background),
+        ("cursor", 12, &theme.cursor),
+    ];
+    let sequences = named
+        .iter()
+        .filter_map(|(_, code, color)| Some(format!("\x1b]{code};{}", x11(color.as_ref()?))))
+        .chain(
+            theme
+                .ansi
+                .iter()
+                .map(|(index, color)| format!("\x1b]4;{index};{}", x11(color))),
+        )
+        .map(String::into_bytes);
+    write_sequences(sequences)?;
+
+    let palette = diagnostics.query(
+        options,
+        extended_color_palette,
+        record_extended_color_palette,
+    )?;
+    let actual = [
+        Some(&palette.foreground),
+        Some(&palette.background),
+        palette.cursor.as_ref(),
+    ];
+    let mut mismatches = Vec::new();
+    for ((name, _, expected), actual) in named.iter().zip(actual) {
+        if let Some(expected) = expected {
+            mismatches.extend(mismatch(name, expected, actual));
+        }
+    }
+    for (index, expected) in &theme.ansi {
+        let actual = palette.ansi[usize::from(*index)].as_ref();
+        mismatches.extend(mismatch(&format!("color {index}"), expected, actual));
+    }
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(CliError::Other(format!(
+            "the terminal did not apply all colors:\n  {}",
+            mismatches.join("\n  ")
+        )))
+    }
+}
+
+// Terminals may store colors with less precision, so we only compare 8 bits per channel.
+fn mismatch(name: &str, expected: &Color, actual: Option<&Color>) -> Option<String> {
+    match actual {
+        Some(actual) if actual.scale_to_8bit() == expected.scale_to_8bit() => None,
+        Some(actual) => Some(format!(
+            "{name} is {} instead of {}",
+            Hex(actual),
+            Hex(expected)
+        )),
+        None => Some(format!("{name} could not be read back")),
+    }
+}
+
+/// Formats a color as an X11 color string, which all terminals supporting `OSC 10` understand.
+fn x11(color: &Color) -> String {
+    format!("rgb:{:04x}/{:04x}/{:04x}", color.r, color.g, color.b)
+}
+
+/// Writes `OSC` sequences using the string terminator and passthrough picked for the terminal.
+fn write_sequences(sequences: impl IntoIterator<Item = Vec<u8>>) -> io::Result<()> {
+    // Programs using the cache should pick up the new colors.
+    _ = cache::invalidate(&CacheOptions::default());
+    let quirks = terminal_quirks();
+    let mut terminal = terminal_trx::terminal()?;
+    let mut terminal = terminal.lock();
+    for mut sequence in sequences {
+        sequence.extend_from_slice(quirks.string_terminator.as_bytes());
+        quirks.write_all(&mut terminal, &sequence)?;
+    }
+    terminal.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_indexed_colors() {
+        assert_eq!(
+            Ok((1, Color::rgb(0xf3f3, 0x8b8b, 0xa8a8))),
+            parse_indexed_color("1=#f38ba8")
+        );
+        assert!(parse_indexed_color("256=#f38ba8").is_err());
+        assert!(parse_indexed_color("#f38ba8").is_err());
+    }
+
+    #[test]
+    fn formats_x11_colors() {
+        assert_eq!("rgb:ffff/8080/0000", x11(&Color::rgb(0xffff, 0x8080, 0)));
+    }
+}
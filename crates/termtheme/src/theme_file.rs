@@ -0,0 +1,198 @@
+use serde::de::IntoDeserializer as _;
+use serde::Deserialize;
+use std::collections::HashMap;
+use terminal_colorsaurus::Color;
+
+/// The colors to set. Colors that are missing are left unchanged.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Theme {
+    pub(crate) foreground: Option<Color>,
+    pub(crate) background: Option<Color>,
+    pub(crate) cursor: Option<Color>,
+    /// Indexed colors as set by `OSC 4`.
+    pub(crate) ansi: Vec<(u8, Color)>,
+}
+
+/// Parses a color such as `#1e1e2e` or `rgb:1e/1e/2e`.
+pub(crate) fn parse_color(input: &str) -> Result<Color, String> {
+    Color::deserialize(input.into_deserializer())
+        .map_err(|_: serde::de::value::Error| format!("invalid color '{input}'"))
+}
+
+/// Parses a theme file. Files starting with `{` are read as the JSON
+/// emitted by `termtheme --format json` or `termtheme palette --format json`,
+/// everything else as a base16 scheme (YAML).
+pub(crate) fn parse_theme(input: &str) -> Result<Theme, String> {
+    if input.trim_start().starts_with('{') {
+        parse_json(input)
+    } else {
+        parse_base16(input)
+    }
+}
+
+#[derive(Deserialize)]
+struct JsonTheme {
+    foreground: Option<JsonColor>,
+    background: Option<JsonColor>,
+    cursor: Option<JsonColor>,
+    #[serde(default)]
+    ansi: Vec<Option<JsonColor>>,
+}
+
+// `termtheme palette` wraps the colors in swatches.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonColor {
+    Color(Color),
+    Swatch { color: Color },
+}
+
+impl From<JsonColor> for Color {
+    fn from(value: JsonColor) -> Self {
+        match value {
+            JsonColor::Color(color) | JsonColor::Swatch { color } => color,
+        }
+    }
+}
+
+fn parse_json(input: &str) -> Result<Theme, String> {
+    let theme: JsonTheme = serde_json::from_str(input).map_err(|e| e.to_string())?;
+    let ansi = theme
+        .ansi
+        .into_iter()
+        .zip(0..=u8::MAX)
+        .filter_map(|(color, index)| Some((index, color?.into())))
+        .collect();
+    Ok(Theme {
+        foreground: theme.foreground.map(Color::from),
+        background: theme.background.map(Color::from),
+        cursor: theme.cursor.map(Color::from),
+        ansi,
+    })
+}
+
+/// The base16 colors used for the 16 ANSI colors, following base16-shell.
+const BASE16_ANSI: [&str; 16] = [
+    "base00", "base08", "base0B", "base0A", "base0D", "base0E", "base0C", "base05", "base03",
+    "base08", "base0B", "base0A", "base0D", "base0E", "base0C", "base07",
+];
+
+// base16 schemes are flat `key: "value"` pairs (nested under `palette:` in newer schemes),
+// which doesn't warrant a full YAML parser.
+fn parse_base16(input: &str) -> Result<Theme, String> {
+    let mut colors = HashMap::new();
+    for line in input.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let key = key.trim();
+        if key.len() == 6 && key.get(..4).is_some_and(|k| k.eq_ignore_ascii_case("base")) {
+            let value = base16_value(value);
+            let value = value.strip_prefix('#').unwrap_or(value);
+            colors.insert(key.to_ascii_lowercase(), parse_color(&format!("#{value}"))?);
+        }
+    }
+    let color = |key: &str| {
+        colors
+            .get(&key.to_ascii_lowercase())
+            .cloned()
+            .ok_or_else(|| format!("the base16 scheme is missing '{key}'"))
+    };
+    let ansi = BASE16_ANSI
+        .iter()
+        .zip(0..)
+        .map(|(key, index)| Ok((index, color(key)?)))
+        .collect::<Result<_, String>>()?;
+    Ok(Theme {
+        foreground: Some(color("base05")?),
+        background: Some(color("base00")?),
+        cursor: Some(color("base05")?),
+        ansi,
+    })
+}
+
+// Values are either quoted or end at the first whitespace (e.g. before a comment).
+fn base16_value(value: &str) -> &str {
+    let value = value.trim_start();
+    match value.chars().next() {
+        Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or(""),
+        _ => value.split_whitespace().next().unwrap_or(""),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    const BASE16: &str = r##"
+scheme: "Test"
+author: "Someone"
+base00: "1e1e2e" # background
+base01: '#181825'
+base02: 313244 # comment
+base03: "45475a"
+base04: "585b70"
+base05: "cdd6f4"
+base06: "f5e0dc"
+base07: "b4befe"
+base08: "f38ba8"
+base09: "fab387"
+base0A: "f9e2af"
+base0B: "a6e3a1"
+base0C: "94e2d5"
+base0D: "89b4fa"
+base0E: "cba6f7"
+base0F: "f2cdcd"
+"##;
+
+    #[test]
+    fn parses_colors() {
+        assert_eq!(Ok(Color::rgb(0xffff, 0x8080, 0)), parse_color("#ff8000"));
+        assert_eq!(
+            Ok(Color::rgb(0xffff, 0x8080, 0)),
+            parse_color("rgb:ff/80/00")
+        );
+        assert!(parse_color("orange").is_err());
+    }
+
+    #[test]
+    fn parses_base16_schemes() {
+        let theme = parse_theme(BASE16).unwrap();
+        assert_eq!(parse_color("#1e1e2e").ok(), theme.background);
+        assert_eq!(parse_color("#cdd6f4").ok(), theme.foreground);
+        assert_eq!(16, theme.ansi.len());
+        assert_eq!((1, parse_color("#f38ba8").unwrap()), theme.ansi[1]);
+        assert_eq!((15, parse_color("#b4befe").unwrap()), theme.ansi[15]);
+    }
+
+    #[test]
+    fn parses_nested_base16_schemes() {
+        let nested = BASE16
+            .replace("\nbase", "\n  base")
+            .replace("author", "palette:\n  author");
+        assert_eq!(parse_theme(BASE16), parse_theme(&nested));
+    }
+
+    #[test]
+    fn rejects_incomplete_base16_schemes() {
+        assert!(parse_theme(&BASE16.replace("base0E", "baseXY")).is_err());
+    }
+
+    #[test]
+    fn parses_termtheme_json() {
+        let theme =
+            parse_theme(r##"{"theme":"dark","foreground":"#ffffff","background":"#1e1e2e"}"##)
+                .unwrap();
+        assert_eq!(parse_color("#ffffff").ok(), theme.foreground);
+        assert_eq!(None, theme.cursor);
+
+        let theme = parse_theme(
+            r##"{"foreground":{"color":"#ffffff","contrast":1.0},"background":{"color":"#000000"},
+                "ansi":[null,{"color":"#ff0000"}]}"##,
+        )
+        .unwrap();
+        assert_eq!(parse_color("#ffffff").ok(), theme.foreground);
+        assert_eq!(vec![(1, parse_color("#ff0000").unwrap())], theme.ansi);
+    }
+}
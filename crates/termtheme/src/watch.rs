@@ -0,0 +1,269 @@
+use crate::diagnostics::DiagnosticsArgs;
+use crate::format::{self, Format};
+use crate::{display_error, parse_duration, CliError};
+use std::io::{self, Write as _};
+use std::process::Command;
+use std::thread::sleep;
+use std::time::Duration;
+use terminal_colorsaurus::cache::{self, CacheOptions};
+use terminal_colorsaurus::recording::record_theme_mode_report;
+use terminal_colorsaurus::{
+    color_palette, theme_mode_report, ColorPalette, QueryOptions, ThemeMode,
+};
+
+#[derive(clap::Args, Debug)]
+pub(crate) struct WatchArgs {
+    /// How often to query the terminal if it doesn't send color scheme notifications.
+    #[arg(long, value_parser = parse_duration, default_value = "2s")]
+    interval: Duration,
+    /// Always poll, even if the terminal sends color scheme notifications.
+    #[arg(long)]
+    poll: bool,
+    /// A command to run whenever the theme changes.
+    ///
+    /// The command is run by the shell with TERM_THEME, TERM_FOREGROUND
+    /// and TERM_BACKGROUND set.
+    #[arg(long, value_name = "COMMAND")]
+    exec: Option<String>,
+    /// The output format.
+    #[arg(long, value_enum, default_value_t)]
+    format: Format,
+}
+
+/// Prints the theme and then again whenever it changes.
+/// Only returns if the initial query fails or if the user presses Ctrl+C
+/// (or Ctrl+D) while waiting for a notification.
+pub(crate) fn watch(
+    args: &WatchArgs,
+    options: QueryOptions,
+    diagnostics: &DiagnosticsArgs,
+) -> Result<(), CliError> {
+    let mut report_options = options.clone();
+    report_options.query_color_scheme = true;
+    // Programs using the cache should pick up the colors we just received.
+    let report = diagnostics.query(
+        report_options.clone(),
+        |options| cache::theme_mode_report(options, &CacheOptions::default()),
+        record_theme_mode_report,
+    )?;
+    let mut watcher = Watcher {
+        format: args.format,
+        exec: args.exec.as_deref(),
+        last: None,
+    };
+    watcher.update(report.theme_mode, report.palette.as_ref());
+
+    // Terminals that answer `CSI ? 996 n` also support notifications (mode 2031).
+    if report.reported.is_some() && !args.poll {
+        let mut notifications = Notifications::enable()?;
+        while let Some(theme) = notifications.next()? {
+            // Notifications that arrive while we query the colors end up in the reply
+            // to `CSI ? 996 n`, so the report has the latest mode.
+            match theme_mode_report(report_options.clone()) {
+                Ok(report) => watcher.update(report.theme_mode, report.palette.as_ref()),
+                Err(_) => watcher.update(theme, None),
+            }
+        }
+        Ok(())
+    } else {
+        loop {
+            sleep(args.interval);
+            // Errors are usually transient (e.g. a timeout while the system is busy),
+            // so there's no reason to stop watching.
+            match color_palette(options.clone()) {
+                Ok(palette) => watcher.update(palette.theme_mode(), Some(&palette)),
+                Err(e) => display_error(e),
+            }
+        }
+    }
+}
+
+struct Watcher<'a> {
+    format: Format,
+    exec: Option<&'a str>,
+    last: Option<String>,
+}
+
+impl Watcher<'_> {
+    fn update(&mut self, theme: ThemeMode, palette: Option<&ColorPalette>) {
+        let output = self.format.format(theme, palette);
+        if self.last.as_ref() == Some(&output) {
+            return;
+        }
+        println!("{output}");
+        if self.last.is_some() {
+            // Programs using the cache should pick up the new colors.
+            _ = cache::invalidate(&CacheOptions::default());
+            if let Some(command) = self.exec {
+                run_hook(command, theme, palette);
+            }
+        }
+        self.last = Some(output);
+    }
+}
+
+fn run_hook(command: &str, theme: ThemeMode, palette: Option<&ColorPalette>) {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let status = shell
+        .arg(command)
+        .envs(format::variables(theme, palette))
+        .status();
+    match status {
+        Ok(status) if !status.success() => display_error(format_args!("hook failed: {status}")),
+        Ok(_) => {}
+        Err(e) => display_error(format_args!("failed to run hook: {e}")),
+    }
+}
+
+const ENABLE_NOTIFICATIONS: &[u8] = b"\x1b[?2031h";
+const DISABLE_NOTIFICATIONS: &[u8] = b"\x1b[?2031l";
+const CTRL_C: u8 = 0x03;
+const CTRL_D: u8 = 0x04;
+
+/// Color scheme notifications, enabled for as long as this lives.
+///
+/// Notifications stay enabled between two calls to [`Notifications::next`]
+/// so that we don't miss a change while we query the colors.
+/// They are disabled on drop so that they don't end up in the shell's input after we exit.
+struct Notifications {
+    terminal: terminal_trx::Terminal,
+    parser: NotificationParser,
+}
+
+impl Notifications {
+    fn enable() -> io::Result<Self> {
+        let mut terminal = terminal_trx::terminal()?;
+        terminal.write_all(ENABLE_NOTIFICATIONS)?;
+        terminal.flush()?;
+        Ok(Self {
+            terminal,
+            parser: NotificationParser::default(),
+        })
+    }
+
+    /// Waits for the terminal to report a changed color scheme.
+    /// Returns [`None`] if the user pressed Ctrl+C or Ctrl+D.
+    fn next(&mut self) -> io::Result<Option<ThemeMode>> {
+        let mut terminal = self.terminal.lock();
+        // Raw mode disables the signal keys, so we have to handle Ctrl+C ourselves.
+        let mut terminal = terminal.enable_raw_mode()?;
+        self.parser.read_next(&mut terminal)
+    }
+}
+
+impl Drop for Notifications {
+    fn drop(&mut self) {
+        _ = self.terminal.write_all(DISABLE_NOTIFICATIONS);
+        _ = self.terminal.flush();
+    }
+}
+
+/// Picks notifications of the form `CSI ? 997 ; <mode> n`
+/// (where `<mode>` is 1 for dark and 2 for light) out of the terminal's input.
+/// Everything else (e.g. keys pressed by the user) is ignored.
+#[derive(Default)]
+struct NotificationParser {
+    sequence: Vec<u8>,
+}
+
+impl NotificationParser {
+    // Long enough for any notification, short enough to not grow on garbage.
+    const MAX_LEN: usize = 16;
+
+    /// Reads until a notification arrives. Returns [`None`] on Ctrl+C, Ctrl+D or at the end of the input.
+    ///
+    /// Reads byte by byte so that the input following the notification stays
+    /// in the terminal's buffer for the next call.
+    fn read_next(&mut self, input: &mut impl io::Read) -> io::Result<Option<ThemeMode>> {
+        let mut byte = [0];
+        loop {
+            if input.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            match byte[0] {
+                CTRL_C | CTRL_D => return Ok(None),
+                byte => {
+                    if let Some(theme) = self.push(byte) {
+                        return Ok(Some(theme));
+                    }
+                }
+            }
+        }
+    }
+
+    fn push(&mut self, byte: u8) -> Option<ThemeMode> {
+        if byte == 0x1b {
+            self.sequence.clear();
+        } else if self.sequence.is_empty() {
+            return None;
+        }
+        self.sequence.push(byte);
+        let theme = match self.sequence.as_slice() {
+            b"\x1b[?997;1n" => Some(ThemeMode::Dark),
+            b"\x1b[?997;2n" => Some(ThemeMode::Light),
+            _ => None,
+        };
+        if theme.is_some() || byte == b'n' || self.sequence.len() > Self::MAX_LEN {
+            self.sequence.clear();
+        }
+        theme
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &[u8]) -> Vec<ThemeMode> {
+        let mut parser = NotificationParser::default();
+        input.iter().filter_map(|b| parser.push(*b)).collect()
+    }
+
+    #[test]
+    fn parses_notifications() {
+        assert_eq!(vec![ThemeMode::Dark], parse(b"\x1b[?997;1n"));
+        assert_eq!(
+            vec![ThemeMode::Light, ThemeMode::Dark],
+            parse(b"\x1b[?997;2n\x1b[?997;1n")
+        );
+    }
+
+    #[test]
+    fn ignores_other_input() {
+        assert_eq!(
+            vec![ThemeMode::Light],
+            parse(b"n\x1b[A?997;2n\x1b[?997;3n\x1b\x1b[?997;2n")
+        );
+        assert_eq!(Vec::<ThemeMode>::new(), parse(b"\x1b[?997;1\x1b[?997;"));
+    }
+
+    #[test]
+    fn reads_back_to_back_notifications() {
+        let mut input: &[u8] = b"\x1b[?997;2n\x1b[?997;1nq";
+        let mut parser = NotificationParser::default();
+        assert_eq!(
+            Some(ThemeMode::Light),
+            parser.read_next(&mut input).unwrap()
+        );
+        // The second notification is left for the next call.
+        assert_eq!(b"\x1b[?997;1nq", input);
+        assert_eq!(Some(ThemeMode::Dark), parser.read_next(&mut input).unwrap());
+        assert_eq!(None, parser.read_next(&mut input).unwrap());
+    }
+
+    #[test]
+    fn stops_reading_on_ctrl_c() {
+        let mut input: &[u8] = b"\x1b[?997;\x03\x1b[?997;1n";
+        let mut parser = NotificationParser::default();
+        assert_eq!(None, parser.read_next(&mut input).unwrap());
+        assert_eq!(b"\x1b[?997;1n", input);
+    }
+}
@@ -11,12 +11,18 @@
 //!    Color::rgb(0x1111, 0xaaaa, 0xffff)
 //! );
 //! ```
+//!
+//! ## Optional Dependencies
+//! * [`serde`](https://docs.rs/serde) — Enable this feature to serialize and deserialize [`Color`].
 
 use core::fmt;
 use std::error;
 use std::marker::PhantomData;
 use std::str::from_utf8;
 
+#[cfg(feature = "serde")]
+mod serde;
+
 /// An RGB color with 16 bits per channel and an optional alpha channel.
 #[derive(Debug, Clone, Eq, PartialEq)]
 #[allow(clippy::exhaustive_structs)]
@@ -62,6 +68,31 @@ impl Color {
     }
 }
 
+/// Formats the color as an X11 color string that [`Color::parse`] accepts,
+/// using `rgba:` if the color has a non-default alpha.
+///
+/// ```
+/// # use xterm_color::Color;
+/// assert_eq!("rgb:1111/aaaa/ffff", Color::rgb(0x1111, 0xaaaa, 0xffff).to_string());
+/// ```
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.alpha == u16::MAX {
+            write!(
+                f,
+                "rgb:{:04x}/{:04x}/{:04x}",
+                self.red, self.green, self.blue
+            )
+        } else {
+            write!(
+                f,
+                "rgba:{:04x}/{:04x}/{:04x}/{:04x}",
+                self.red, self.green, self.blue, self.alpha
+            )
+        }
+    }
+}
+
 /// Error which can be returned when parsing a color.
 #[derive(Debug, Clone)]
 pub struct ColorParseError(PhantomData<()>);
@@ -98,7 +129,8 @@ fn xparsecolor(input: &[u8]) -> Option<Color> {
 fn parse_sharp(input: &str) -> Option<Color> {
     const NUM_COMPONENTS: usize = 3;
     let len = input.len();
-    if len % NUM_COMPONENTS == 0 && len <= NUM_COMPONENTS * 4 {
+    // Checking for hex digits up front also ensures that we don't slice in the middle of a char.
+    if len % NUM_COMPONENTS == 0 && len <= NUM_COMPONENTS * 4 && is_hex(input) {
         let chunk_size = input.len() / NUM_COMPONENTS;
         let red = parse_channel_shifted(&input[0..chunk_size])?;
         let green = parse_channel_shifted(&input[chunk_size..chunk_size * 2])?;
@@ -165,7 +197,7 @@ fn parse_rgba(input: &str) -> Option<Color> {
 
 fn parse_channel_scaled(input: &str) -> Option<u16> {
     let len = input.len();
-    if (1..=4).contains(&len) {
+    if (1..=4).contains(&len) && is_hex(input) {
         let max = u32::pow(16, len as u32) - 1;
         let value = u32::from_str_radix(input, 16).ok()?;
         Some((u16::MAX as u32 * value / max) as u16)
@@ -174,6 +206,11 @@ fn parse_channel_scaled(input: &str) -> Option<u16> {
     }
 }
 
+// `from_str_radix` accepts a leading `+` which is not valid in a color string.
+fn is_hex(input: &str) -> bool {
+    input.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
 // Implementation of determining the perceived lightness
 // follows this excellent answer: https://stackoverflow.com/a/56678483
 impl Color {
@@ -185,6 +222,15 @@ impl Color {
         luminance_to_perceived_lightness(self.luminance()) / 100.
     }
 
+    /// The [contrast ratio](https://www.w3.org/TR/WCAG21/#dfn-contrast-ratio) between two colors
+    /// as defined by WCAG 2. Ranges from 1.0 (no contrast) to 21.0 (black on white).
+    ///
+    /// Note that the colors' alpha is ignored.
+    pub fn contrast_ratio(&self, other: &Color) -> f32 {
+        let (a, b) = (self.luminance(), other.luminance());
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+
     /// Luminance (`Y`) calculated using the [CIE XYZ formula](https://en.wikipedia.org/wiki/Relative_luminance).
     fn luminance(&self) -> f32 {
         let r = gamma_function(f32::from(self.red) / f32::from(u16::MAX));
@@ -287,6 +333,7 @@ mod tests {
         assert!(Color::parse(b"rgb:f/f/f/f").is_err()); // Too many channels
         assert!(Color::parse(b"rgb:f//f").is_err()); // Empty channel
         assert!(Color::parse(b"rgb:ffff/ffff/fffff").is_err()); // Too many digits for one channel
+        assert!(Color::parse(b"rgb:+f/f/f").is_err()); // Sign
     }
 
     // Tests adapted from alacritty/vte:
@@ -354,6 +401,21 @@ mod tests {
         assert!(Color::parse(b"#").is_err()); // Empty
         assert!(Color::parse(b"#1234").is_err()); // Not divisible by three
         assert!(Color::parse(b"#123456789ABCDEF").is_err()); // Too many components
+        assert!(Color::parse(b"#+1+2+3").is_err()); // Sign
+        assert!(Color::parse("#é1".as_bytes()).is_err()); // Not ASCII
+    }
+
+    #[test]
+    fn formats_color() {
+        assert_eq!(
+            "rgb:ffff/0000/0a0b",
+            Color::rgb(0xffff, 0, 0x0a0b).to_string()
+        );
+        let color = Color {
+            alpha: 0xcccc,
+            ..Color::rgb(0, 0, 0x4443)
+        };
+        assert_eq!("rgba:0000/0000/4443/cccc", color.to_string());
     }
 
     #[test]
@@ -367,4 +429,14 @@ mod tests {
         let white = Color::rgb(u16::MAX, u16::MAX, u16::MAX);
         assert_eq!(1.0, white.perceived_lightness())
     }
+
+    #[test]
+    fn contrast_ratio_is_symmetric() {
+        let black = Color::rgb(0, 0, 0);
+        let white = Color::rgb(u16::MAX, u16::MAX, u16::MAX);
+        let gray = Color::rgb(0x7777, 0x7777, 0x7777);
+        assert!((black.contrast_ratio(&white) - 21.0).abs() < 1e-4);
+        assert_eq!(white.contrast_ratio(&gray), gray.contrast_ratio(&white));
+        assert_eq!(1.0, gray.contrast_ratio(&gray));
+    }
 }
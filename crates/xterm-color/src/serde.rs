@@ -0,0 +1,238 @@
+use crate::Color;
+use core::fmt;
+use serde::de::{self, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{SerializeStruct as _, Serializer};
+use serde::{Deserialize, Serialize};
+
+const FIELDS: &[&str] = &["red", "green", "blue", "alpha"];
+
+/// Human-readable formats (e.g. JSON) use a string: `#rrggbb` if the color is opaque
+/// and fits into 8 bits per channel and the [`Display`](fmt::Display) representation otherwise.
+/// Other formats use a struct with the fields `red`, `green`, `blue` and `alpha`.
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(&ColorString(self))
+        } else {
+            let mut color = serializer.serialize_struct("Color", FIELDS.len())?;
+            color.serialize_field("red", &self.red)?;
+            color.serialize_field("green", &self.green)?;
+            color.serialize_field("blue", &self.blue)?;
+            color.serialize_field("alpha", &self.alpha)?;
+            color.end()
+        }
+    }
+}
+
+/// Accepts both color strings and the struct representation in human-readable formats.
+/// The `alpha` field is optional and defaults to `0xffff`.
+///
+/// Unlike in [`Color::parse`], `#rrggbb` is scaled to 16 bits per channel
+/// (i.e. `#ffffff` is white) so that serialized colors round-trip.
+/// All other color strings are parsed using [`Color::parse`].
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(ColorVisitor)
+        } else {
+            deserializer.deserialize_struct("Color", FIELDS, ColorVisitor)
+        }
+    }
+}
+
+struct ColorString<'a>(&'a Color);
+
+impl fmt::Display for ColorString<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Color {
+            red,
+            green,
+            blue,
+            alpha,
+        } = *self.0;
+        if alpha == u16::MAX && [red, green, blue].iter().all(|c| c % 0x101 == 0) {
+            write!(f, "#{:02x}{:02x}{:02x}", red >> 8, green >> 8, blue >> 8)
+        } else {
+            fmt::Display::fmt(self.0, f)
+        }
+    }
+}
+
+fn parse_color_string(input: &str) -> Option<Color> {
+    match input.strip_prefix('#') {
+        // Checking for hex digits up front also ensures that we don't slice in the middle of a char.
+        Some(hex) if hex.len() == 6 && hex.bytes().all(|b| b.is_ascii_hexdigit()) => {
+            let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+            let scale = |c: u8| u16::from(c) * 0x101;
+            Some(Color::rgb(
+                scale(channel(0)?),
+                scale(channel(2)?),
+                scale(channel(4)?),
+            ))
+        }
+        _ => Color::parse(input.as_bytes()).ok(),
+    }
+}
+
+struct ColorVisitor;
+
+impl<'de> Visitor<'de> for ColorVisitor {
+    type Value = Color;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(
+            "an X11 color string or a struct with the fields `red`, `green`, `blue` and `alpha`",
+        )
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        parse_color_string(v).ok_or_else(|| E::invalid_value(de::Unexpected::Str(v), &self))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut channel = |index| {
+            seq.next_element()?
+                .ok_or_else(|| de::Error::invalid_length(index, &self))
+        };
+        let mut color = Color::rgb(channel(0)?, channel(1)?, channel(2)?);
+        if let Some(alpha) = seq.next_element()? {
+            color.alpha = alpha;
+        }
+        Ok(color)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut channels = [None; 4];
+        while let Some(key) = map.next_key::<Field>()? {
+            let slot = &mut channels[key as usize];
+            if slot.is_some() {
+                return Err(de::Error::duplicate_field(FIELDS[key as usize]));
+            }
+            *slot = Some(map.next_value()?);
+        }
+        let [red, green, blue, alpha] = channels;
+        Ok(Color {
+            red: red.ok_or_else(|| de::Error::missing_field("red"))?,
+            green: green.ok_or_else(|| de::Error::missing_field("green"))?,
+            blue: blue.ok_or_else(|| de::Error::missing_field("blue"))?,
+            alpha: alpha.unwrap_or(u16::MAX),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(field_identifier, rename_all = "lowercase")]
+enum Field {
+    Red,
+    Green,
+    Blue,
+    Alpha,
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use serde_test::{assert_tokens, Configure as _, Token};
+
+    #[test]
+    fn serializes_as_color_string() {
+        assert_eq!(
+            r##""#11aaff""##,
+            serde_json::to_string(&Color::rgb(0x1111, 0xaaaa, 0xffff)).unwrap()
+        );
+        let transparent = Color {
+            alpha: 0,
+            ..Color::rgb(0, 0, 0)
+        };
+        assert_eq!(
+            r#""rgba:0000/0000/0000/0000""#,
+            serde_json::to_string(&transparent).unwrap()
+        );
+        assert_eq!(
+            r##""#ff8000""##,
+            serde_json::to_string(&Color::rgb(0xffff, 0x8080, 0)).unwrap()
+        );
+        assert_eq!(
+            r#""rgb:ffff/8081/0000""#,
+            serde_json::to_string(&Color::rgb(0xffff, 0x8081, 0)).unwrap()
+        );
+        let translucent = Color {
+            alpha: 0x8080,
+            ..Color::rgb(0xffff, 0xffff, 0xffff)
+        };
+        assert_eq!(
+            r#""rgba:ffff/ffff/ffff/8080""#,
+            serde_json::to_string(&translucent).unwrap()
+        );
+    }
+
+    #[test]
+    fn deserializes_strings_and_structs() {
+        let expected = Color::rgb(0x1111, 0xaaaa, 0xffff);
+        for input in [
+            r#""rgb:11/aa/ff""#,
+            r##""#1111aaaaffff""##,
+            r##""#11aaff""##,
+            r#"{"red": 4369, "green": 43690, "blue": 65535}"#,
+            r#"{"red": 4369, "green": 43690, "blue": 65535, "alpha": 65535}"#,
+            "[4369, 43690, 65535]",
+        ] {
+            assert_eq!(
+                expected,
+                serde_json::from_str::<Color>(input).unwrap(),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_colors() {
+        for input in [
+            r#""rgb:11/aa""#,
+            r#""red""#,
+            r#"{"red": 0, "green": 0}"#,
+            "[0, 0]",
+        ] {
+            assert!(serde_json::from_str::<Color>(input).is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn round_trips_as_json() {
+        for color in [
+            Color::rgb(0, 0, 0),
+            Color::rgb(0xffff, 0xffff, 0xffff),
+            Color::rgb(0x1234, 0x5678, 0x9abc),
+            Color {
+                alpha: 0xcccc,
+                ..Color::rgb(0, 0, 0x4444)
+            },
+        ] {
+            let json = serde_json::to_string(&color).unwrap();
+            assert_eq!(color, serde_json::from_str(&json).unwrap());
+        }
+    }
+
+    #[test]
+    fn uses_struct_in_compact_formats() {
+        assert_tokens(
+            &Color::rgb(1, 2, 3).compact(),
+            &[
+                Token::Struct {
+                    name: "Color",
+                    len: 4,
+                },
+                Token::Str("red"),
+                Token::U16(1),
+                Token::Str("green"),
+                Token::U16(2),
+                Token::Str("blue"),
+                Token::U16(3),
+                Token::Str("alpha"),
+                Token::U16(u16::MAX),
+                Token::StructEnd,
+            ],
+        );
+    }
+}
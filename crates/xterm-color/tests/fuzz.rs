@@ -0,0 +1,87 @@
+//! Property tests for the color parser.
+//! Replies to color queries can be forged by anything that can write to the terminal,
+//! so the parser needs to cope with arbitrary input.
+
+use proptest::prelude::*;
+use proptest::sample::select;
+use xterm_color::Color;
+
+// Color strings reported by the terminals in the terminal survey.
+const CORPUS: &[&str] = &[
+    "rgb:0000/0000/0000",
+    "rgb:ffff/ffff/ffff",
+    "rgb:dcdc/dcdc/cccc",
+    "rgb:1e1e/1e1e/2e2e",
+    "rgb:f/e/d",
+    "rgb:11/aa/ff",
+    "rgb:f/ed1/cb23",
+    "rgba:0000/0000/4443/cccc",
+    "#1af",
+    "#11aaff",
+    "#110aa0ff0",
+    "#1100aa00ff00",
+];
+
+fn color() -> impl Strategy<Value = Color> {
+    (any::<u16>(), any::<u16>(), any::<u16>(), any::<u16>()).prop_map(
+        |(red, green, blue, alpha)| Color {
+            red,
+            green,
+            blue,
+            alpha,
+        },
+    )
+}
+
+// Seeds from the corpus with a few bytes replaced, inserted or removed.
+fn mutated_corpus() -> impl Strategy<Value = Vec<u8>> {
+    (
+        select(CORPUS),
+        prop::collection::vec((any::<prop::sample::Index>(), any::<u8>(), 0..3u8), 0..4),
+    )
+        .prop_map(|(seed, mutations)| {
+            let mut input = seed.as_bytes().to_vec();
+            for (index, byte, kind) in mutations {
+                let position = index.index(input.len() + 1);
+                match kind {
+                    0 if position < input.len() => input[position] = byte,
+                    1 => input.insert(position, byte),
+                    _ if position < input.len() => _ = input.remove(position),
+                    _ => {}
+                }
+            }
+            input
+        })
+}
+
+proptest! {
+    #[test]
+    fn parse_does_not_panic(input in prop::collection::vec(any::<u8>(), 0..32)) {
+        _ = Color::parse(&input);
+    }
+
+    // Multi-byte characters are unlikely to be generated from arbitrary bytes.
+    #[test]
+    fn parse_unicode_does_not_panic(input in "(#|rgb:|rgba:)\\PC{0,16}") {
+        _ = Color::parse(input.as_bytes());
+    }
+
+    #[test]
+    fn parse_mutated_corpus_does_not_panic(input in mutated_corpus()) {
+        if let Ok(color) = Color::parse(&input) {
+            prop_assert_eq!(Some(color.clone()), Color::parse(color.to_string().as_bytes()).ok());
+        }
+    }
+
+    #[test]
+    fn format_round_trips(color in color()) {
+        prop_assert_eq!(Some(color.clone()), Color::parse(color.to_string().as_bytes()).ok());
+    }
+}
+
+#[test]
+fn corpus_parses() {
+    for input in CORPUS {
+        assert!(Color::parse(input.as_bytes()).is_ok(), "{input}");
+    }
+}